@@ -0,0 +1,3 @@
+-- Add Status column to Subscriptions table.
+-- The column is nullable for now, so that existing rows can be backfilled first.
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
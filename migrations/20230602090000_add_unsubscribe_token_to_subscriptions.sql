@@ -0,0 +1,14 @@
+-- Add per-subscriber Unsubscribe Token column to Subscriptions table.
+-- Wrap the whole migration in a transaction to make sure
+-- it succeeds or fails atomically.
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+    -- Backfill `unsubscribe_token` for historical entries. `gen_random_uuid` draws from
+    -- a cryptographically secure generator, unlike `random`.
+    UPDATE subscriptions
+        SET unsubscribe_token = replace(gen_random_uuid()::text, '-', '')
+        WHERE unsubscribe_token IS NULL;
+    -- Make `unsubscribe_token` mandatory and unique
+    ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+COMMIT;
@@ -0,0 +1,12 @@
+-- Create Issue Delivery Queue table.
+-- Each row is a pending delivery of an issue to a single subscriber.
+CREATE TABLE issue_delivery_queue(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    -- Number of failed delivery attempts so far
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    -- The task is not picked up by workers before this moment
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
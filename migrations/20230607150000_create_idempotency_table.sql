@@ -0,0 +1,17 @@
+-- Create Idempotency table.
+-- Stores the HTTP response sent for each (username, idempotency key) pair, so that it
+-- can be replayed if the client retries the request.
+CREATE TYPE header_pair AS (
+    name TEXT,
+    value BYTEA
+);
+CREATE TABLE idempotency (
+    username TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    -- Response columns are NULL while the first request is still being processed
+    response_status_code SMALLINT NULL,
+    response_headers header_pair[] NULL,
+    response_body BYTEA NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (username, idempotency_key)
+);
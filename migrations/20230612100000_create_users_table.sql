@@ -0,0 +1,8 @@
+-- Create Users table.
+-- Passwords are stored as Argon2id hashes in PHC string format, which carries
+-- the algorithm, its parameters and the salt along with the hash itself.
+CREATE TABLE users (
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Key idempotency records by user id instead of username.
+-- Saved responses are only useful for a few minutes, so existing ones are dropped
+-- instead of being matched to users.
+BEGIN;
+    DELETE FROM idempotency;
+    ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+    ALTER TABLE idempotency DROP COLUMN username;
+    ALTER TABLE idempotency
+        ADD COLUMN user_id uuid NOT NULL REFERENCES users (user_id);
+    ALTER TABLE idempotency ADD PRIMARY KEY (user_id, idempotency_key);
+COMMIT;
@@ -0,0 +1,8 @@
+-- Create Sessions table.
+-- Server-side state of cookie sessions: the cookie only carries `session_key`.
+CREATE TABLE sessions (
+    session_key TEXT PRIMARY KEY,
+    state JSONB NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,14 @@
+-- Create API Keys table.
+-- Keys authenticate machine clients with `Authorization: Bearer <key>`.
+-- Only the SHA-256 hash of a key is stored: the key itself is shown once, when created.
+CREATE TABLE api_keys (
+    key_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz,
+    last_used_at timestamptz,
+    revoked_at timestamptz
+);
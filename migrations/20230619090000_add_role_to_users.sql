@@ -0,0 +1,9 @@
+-- Add role to users.
+-- Users created so far are admins with full access, so they become owners.
+BEGIN;
+    ALTER TABLE users ADD COLUMN role TEXT NULL;
+    UPDATE users SET role = 'owner';
+    ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+    ALTER TABLE users
+        ADD CONSTRAINT users_role_check CHECK (role IN ('owner', 'editor', 'viewer'));
+COMMIT;
@@ -0,0 +1,3 @@
+-- Index Subscriptions by signup time.
+-- Subscriber lists are paginated by `(subscribed_at, id)` keyset.
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
@@ -0,0 +1,3 @@
+-- Add Confirmed At column to Subscriptions table.
+-- The confirmation time of subscribers confirmed so far, or imported, is unknown: it stays NULL.
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
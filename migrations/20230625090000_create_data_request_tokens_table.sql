@@ -0,0 +1,10 @@
+-- Create Data Request Tokens table.
+-- Each row lets the holder of the link emailed to a subscriber access or erase their data.
+CREATE TABLE data_request_tokens(
+    data_request_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    kind TEXT NOT NULL CHECK (kind IN ('access', 'erasure')),
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (data_request_token)
+);
@@ -0,0 +1,9 @@
+-- Create Subscriber Erasures table.
+-- Audit record of erasures requested by subscribers: it must hold nothing identifying them.
+CREATE TABLE subscriber_erasures(
+    erasure_id uuid NOT NULL,
+    erased_at timestamptz NOT NULL,
+    -- Number of rows deleted from each table, by table name
+    deleted_rows jsonb NOT NULL,
+    PRIMARY KEY (erasure_id)
+);
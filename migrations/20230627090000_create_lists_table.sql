@@ -0,0 +1,13 @@
+-- Create Lists table, with the newsletters we publish.
+-- Each list is a topic subscribers choose to receive, and issues are published to.
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (list_id)
+);
+INSERT INTO lists (list_id, slug, name)
+VALUES
+    (gen_random_uuid(), 'weekly-digest', 'Weekly digest'),
+    (gen_random_uuid(), 'release-notes', 'Release notes'),
+    (gen_random_uuid(), 'events', 'Events');
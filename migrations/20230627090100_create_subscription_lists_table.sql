@@ -0,0 +1,14 @@
+-- Create Subscription Lists table.
+-- Each row subscribes a subscriber to a list. Subscribers so far received every issue,
+-- so they are subscribed to every list.
+BEGIN;
+    CREATE TABLE subscription_lists(
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id),
+        list_id uuid NOT NULL
+            REFERENCES lists (list_id),
+        PRIMARY KEY (subscriber_id, list_id)
+    );
+    INSERT INTO subscription_lists (subscriber_id, list_id)
+    SELECT subscriptions.id, lists.list_id FROM subscriptions CROSS JOIN lists;
+COMMIT;
@@ -0,0 +1,5 @@
+-- Add Delivery Frequency column to Subscriptions table.
+-- Subscribers so far received every issue as soon as it was published.
+ALTER TABLE subscriptions
+    ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'immediately'
+        CHECK (delivery_frequency IN ('immediately', 'weekly'));
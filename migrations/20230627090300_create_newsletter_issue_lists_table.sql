@@ -0,0 +1,15 @@
+-- Create Newsletter Issue Lists table.
+-- Each row publishes an issue to a list. Issues so far went to every subscriber,
+-- so they are published to every list.
+BEGIN;
+    CREATE TABLE newsletter_issue_lists(
+        newsletter_issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (newsletter_issue_id),
+        list_id uuid NOT NULL
+            REFERENCES lists (list_id),
+        PRIMARY KEY (newsletter_issue_id, list_id)
+    );
+    INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+    SELECT newsletter_issues.newsletter_issue_id, lists.list_id
+    FROM newsletter_issues CROSS JOIN lists;
+COMMIT;
@@ -0,0 +1,15 @@
+-- Add Status and schedule columns to Newsletter Issues table.
+-- Issues so far were queued for delivery as soon as they were stored: they are published.
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+    UPDATE newsletter_issues SET status = 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+    ALTER TABLE newsletter_issues
+        ADD CONSTRAINT newsletter_issues_status_check
+            CHECK (status IN ('scheduled', 'published', 'cancelled'));
+    -- When a scheduled issue is due, and the IANA time zone the editor scheduled it in
+    ALTER TABLE newsletter_issues ADD COLUMN scheduled_for timestamptz NULL;
+    ALTER TABLE newsletter_issues ADD COLUMN scheduled_time_zone TEXT NULL;
+    -- Scheduled issues are only published, and queued for delivery, once they are due
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+COMMIT;
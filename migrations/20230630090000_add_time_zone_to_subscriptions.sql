@@ -0,0 +1,4 @@
+-- Add Time Zone column to Subscriptions table.
+-- IANA name, e.g. `Europe/Berlin`, checked against `pg_timezone_names` by the application.
+-- Subscribers so far didn't tell theirs: NULL means the time zone of the issue schedule.
+ALTER TABLE subscriptions ADD COLUMN time_zone TEXT NULL;
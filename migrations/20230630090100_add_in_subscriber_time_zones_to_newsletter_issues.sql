@@ -0,0 +1,5 @@
+-- Add In Subscriber Time Zones column to Newsletter Issues table.
+-- Scheduled issues with it are delivered at their local time in the time zone of each subscriber.
+-- Issues so far were delivered to everyone at once.
+ALTER TABLE newsletter_issues
+    ADD COLUMN in_subscriber_time_zones BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Add Email column to Users table.
+-- Test sends of draft issues go to it. Admins so far didn't have one: they add it from the admin pages.
+ALTER TABLE users ADD COLUMN email TEXT NULL;
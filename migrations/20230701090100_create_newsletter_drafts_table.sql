@@ -0,0 +1,7 @@
+-- Create Newsletter Drafts table.
+-- The content of a draft is in its revisions: the draft only ties them together.
+CREATE TABLE newsletter_drafts (
+    draft_id uuid PRIMARY KEY,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL
+);
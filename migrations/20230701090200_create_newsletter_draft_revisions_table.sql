@@ -0,0 +1,14 @@
+-- Create Newsletter Draft Revisions table.
+-- Every save of a draft is a new revision, numbered from 1 within the draft.
+-- Revisions outlive their author: removing an admin only forgets who wrote them.
+CREATE TABLE newsletter_draft_revisions (
+    draft_id uuid NOT NULL REFERENCES newsletter_drafts (draft_id) ON DELETE CASCADE,
+    revision INT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    author_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    autosaved BOOLEAN NOT NULL,
+    saved_at timestamptz NOT NULL,
+    PRIMARY KEY (draft_id, revision)
+);
@@ -0,0 +1,375 @@
+//! Contains API keys, authenticating machine clients with `Authorization: Bearer` header.
+use super::role::{Permission, Role};
+use crate::utils::error_chain_fmt;
+use actix_web::dev::Payload;
+use actix_web::http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Permission granted to an API key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    SubscribersRead,
+    SubscribersWrite,
+    NewslettersPublish,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [
+        Scope::SubscribersRead,
+        Scope::SubscribersWrite,
+        Scope::NewslettersPublish,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::SubscribersRead => "subscribers:read",
+            Scope::SubscribersWrite => "subscribers:write",
+            Scope::NewslettersPublish => "newsletters:publish",
+        }
+    }
+
+    /// Return the permission the role of the key's creator must grant for the scope to be usable.
+    pub fn permission(&self) -> Permission {
+        match self {
+            Scope::SubscribersRead => Permission::ViewSubscribers,
+            Scope::SubscribersWrite => Permission::ManageSubscribers,
+            Scope::NewslettersPublish => Permission::PublishNewsletters,
+        }
+    }
+
+    /// Parse the scope from its name, e.g. `subscribers:write`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("`{}` is not a known scope.", s))
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Scope a route requires from the API key of a request, set by the type parameter of `ApiKey`.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+/// Requires `subscribers:read` scope.
+pub struct ReadSubscribers;
+
+/// Requires `subscribers:write` scope.
+pub struct WriteSubscribers;
+
+/// Requires `newsletters:publish` scope.
+pub struct PublishNewsletters;
+
+impl RequiredScope for ReadSubscribers {
+    const SCOPE: Scope = Scope::SubscribersRead;
+}
+
+impl RequiredScope for WriteSubscribers {
+    const SCOPE: Scope = Scope::SubscribersWrite;
+}
+
+impl RequiredScope for PublishNewsletters {
+    const SCOPE: Scope = Scope::NewslettersPublish;
+}
+
+/// Valid API key of the request, granting scope `S`.
+///
+/// Add it to handler arguments to guard the route: requests without a valid key are rejected
+/// with `401 UNAUTHORIZED`, and requests whose key lacks the scope with `403 FORBIDDEN`.
+pub struct ApiKey<S> {
+    pub key_id: Uuid,
+    /// Id of the user who created the key. Requests act on their behalf.
+    pub user_id: Uuid,
+    scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> ApiKey<S> {
+    /// Authenticate the `Authorization: Bearer` header, and check the key grants scope `S`.
+    /// Record the use of the key.
+    ///
+    /// The scope is only granted while the role of the key's creator permits it, so that
+    /// demoting a user also restricts their keys.
+    #[tracing::instrument(
+        name = "Authenticate API key",
+        skip_all,
+        fields(scope = %S::SCOPE, key_id = tracing::field::Empty)
+    )]
+    pub async fn authenticate(headers: &HeaderMap, pool: &PgPool) -> Result<Self, ApiKeyError> {
+        let key = bearer_token(headers).map_err(ApiKeyError::InvalidKey)?;
+        let row = sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET last_used_at = now()
+            FROM users
+            WHERE api_keys.user_id = users.user_id
+                AND key_hash = $1
+                AND revoked_at IS NULL
+                AND (expires_at IS NULL OR expires_at > now())
+            RETURNING api_keys.key_id, api_keys.user_id, api_keys.scopes, users.role
+            "#,
+            hash_api_key(&key)
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to perform a query to retrieve an API key.")?
+        .ok_or_else(|| ApiKeyError::InvalidKey("Unknown, expired or revoked API key.".into()))?;
+        tracing::Span::current().record("key_id", tracing::field::display(&row.key_id));
+
+        let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+        if !row.scopes.iter().any(|s| s == S::SCOPE.as_str())
+            || !role.permits(S::SCOPE.permission())
+        {
+            return Err(ApiKeyError::InsufficientScope(S::SCOPE));
+        }
+        Ok(Self {
+            key_id: row.key_id,
+            user_id: row.user_id,
+            scope: PhantomData,
+        })
+    }
+}
+
+impl<S: RequiredScope + 'static> FromRequest for ApiKey<S> {
+    type Error = ApiKeyError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .context("Database connection pool is missing from application state.")?;
+            Self::authenticate(req.headers(), pool).await
+        })
+    }
+}
+
+/// Failure of `ApiKey` authentication.
+#[derive(thiserror::Error)]
+pub enum ApiKeyError {
+    #[error("{0}")]
+    InvalidKey(String),
+    #[error("The API key doesn't grant `{0}` scope.")]
+    InsufficientScope(Scope),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::InvalidKey(_) => StatusCode::UNAUTHORIZED,
+            ApiKeyError::InsufficientScope(_) => StatusCode::FORBIDDEN,
+            ApiKeyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Tell the client how to authenticate, as described by RFC 6750.
+    fn error_response(&self) -> HttpResponse {
+        let challenge = match self {
+            ApiKeyError::InvalidKey(_) => r#"Bearer error="invalid_token""#.to_string(),
+            ApiKeyError::InsufficientScope(scope) => {
+                format!(r#"Bearer error="insufficient_scope", scope="{}""#, scope)
+            }
+            ApiKeyError::UnexpectedError(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let mut response = HttpResponse::new(self.status_code());
+        response.headers_mut().insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_str(&challenge).expect("Invalid challenge header value."),
+        );
+        response
+    }
+}
+
+/// API key as stored for listing: the key itself is never stored.
+pub struct StoredApiKey {
+    pub key_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Create an API key for the user, granting `scopes` until `expires_at`, if set.
+/// Return the id of the key, and the key itself: it can't be retrieved later.
+#[tracing::instrument(name = "Create API key", skip(pool))]
+pub async fn create_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[Scope],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(Uuid, Secret<String>), anyhow::Error> {
+    let key_id = Uuid::new_v4();
+    let key = generate_api_key();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (key_id, user_id, name, key_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now(), $6)
+        "#,
+        key_id,
+        user_id,
+        name,
+        hash_api_key(&key),
+        &scopes,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API key.")?;
+
+    Ok((key_id, key))
+}
+
+/// Return API keys created by the user, newest first.
+#[tracing::instrument(name = "Get API keys", skip(pool))]
+pub async fn get_api_keys(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<StoredApiKey>, anyhow::Error> {
+    sqlx::query_as!(
+        StoredApiKey,
+        r#"
+        SELECT key_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        FROM api_keys
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve API keys.")
+}
+
+/// Revoke the API key, if the user created it. Revoking a key twice keeps the first revocation time.
+#[tracing::instrument(name = "Revoke API key", skip(pool))]
+pub async fn revoke_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    key_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = COALESCE(revoked_at, now())
+        WHERE key_id = $1 AND user_id = $2
+        "#,
+        key_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API key.")?;
+
+    Ok(())
+}
+
+/// Generate a random API key. The prefix makes leaked keys easy to spot by secret scanners.
+fn generate_api_key() -> Secret<String> {
+    let mut rng = thread_rng();
+    let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("nl_{}", random))
+}
+
+/// Return hex-encoded SHA-256 hash of `key`.
+/// Keys are long and random, so a fast hash is enough: there is nothing to brute-force.
+fn hash_api_key(key: &Secret<String>) -> String {
+    hex::encode(Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+/// Extract the key from the `Authorization` header, using Bearer authentication scheme.
+fn bearer_token(headers: &HeaderMap) -> Result<Secret<String>, String> {
+    let header_value = headers
+        .get("Authorization")
+        .ok_or("The 'Authorization' header was missing.")?
+        .to_str()
+        .map_err(|_| "The 'Authorization' header was not a valid UTF8 string.")?;
+    let token = header_value
+        .strip_prefix("Bearer ")
+        .ok_or("The authorization scheme was not 'Bearer'.")?;
+
+    Ok(Secret::new(token.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bearer_token, generate_api_key, hash_api_key, Scope};
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use claim::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    #[test]
+    fn scopes_round_trip_through_their_names() {
+        for scope in Scope::ALL {
+            assert_eq!(Scope::parse(scope.as_str()), Ok(scope));
+        }
+        assert_err!(Scope::parse("subscribers:delete"));
+    }
+
+    #[test]
+    fn generated_keys_are_prefixed_and_unique() {
+        let first = generate_api_key();
+        let second = generate_api_key();
+
+        assert!(first.expose_secret().starts_with("nl_"));
+        assert_eq!(first.expose_secret().len(), 43);
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+
+    #[test]
+    fn hash_is_stable_and_hides_the_key() {
+        let key = Secret::new("nl_some-key".to_string());
+
+        assert_eq!(hash_api_key(&key), hash_api_key(&key));
+        assert_eq!(hash_api_key(&key).len(), 64);
+        assert!(!hash_api_key(&key).contains("some-key"));
+    }
+
+    #[test]
+    fn only_bearer_scheme_is_accepted() {
+        let mut headers = HeaderMap::new();
+        assert_err!(bearer_token(&headers));
+
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic Zm9vOmJhcg=="),
+        );
+        assert_err!(bearer_token(&headers));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer nl_abc"));
+        let token = assert_ok!(bearer_token(&headers));
+        assert_eq!(token.expose_secret(), "nl_abc");
+    }
+}
@@ -0,0 +1,20 @@
+mod api_key;
+mod middleware;
+mod password;
+mod role;
+mod users;
+
+pub use api_key::{
+    create_api_key, get_api_keys, revoke_api_key, ApiKey, ApiKeyError, PublishNewsletters,
+    ReadSubscribers, RequiredScope, Scope, StoredApiKey, WriteSubscribers,
+};
+pub use middleware::{reject_anonymous_users, UserId};
+pub use password::{
+    change_password, compute_password_hash, validate_credentials, validate_new_password, AuthError,
+    Credentials, NewPasswordError, MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH,
+};
+pub use role::{get_role, require_permission, Permission, Role};
+pub use users::{
+    bootstrap_admin, change_role, create_user, get_user_email, get_users, invite_user, remove_user,
+    set_user_email, StoredUser, UserManagementError,
+};
@@ -0,0 +1,278 @@
+//! Contains password-based authentication of users.
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Credentials provided by a client.
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Failure of `validate_credentials`.
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Return the id of the user `credentials` belong to.
+///
+/// Unknown usernames are checked against a dummy hash, so that the response time
+/// doesn't reveal which usernames exist.
+///
+/// Passwords hashed with outdated parameters are re-hashed with the current ones once verified.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = Secret::new(DUMMY_PASSWORD_HASH.to_string());
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    let is_outdated = is_outdated(&expected_password_hash);
+    let password = credentials.password.clone();
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")??;
+
+    let user_id = user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username."))
+        .map_err(AuthError::InvalidCredentials)?;
+    if is_outdated {
+        // The user is authenticated anyway: don't fail the login if the upgrade fails.
+        if let Err(e) = change_password(user_id, password, pool).await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to upgrade an outdated password hash.");
+        }
+    }
+
+    Ok(user_id)
+}
+
+/// Store the hash of `password`, computed with the current parameters, for the user.
+#[tracing::instrument(name = "Change password", skip(password, pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to change user's password in the database.")?;
+
+    Ok(())
+}
+
+/// Reason why a new password is rejected by `validate_new_password`.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum NewPasswordError {
+    #[error("The new password must be at least {MIN_PASSWORD_LENGTH} characters long.")]
+    TooShort,
+    #[error("The new password must be at most {MAX_PASSWORD_LENGTH} characters long.")]
+    TooLong,
+}
+
+/// Minimum length of a password, in characters.
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+/// Maximum length of a password, in characters.
+/// Long enough for passphrases, short enough not to turn hashing into a DoS vector.
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Check that `password` satisfies the length limits.
+pub fn validate_new_password(password: &Secret<String>) -> Result<(), NewPasswordError> {
+    let length = password.expose_secret().chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        Err(NewPasswordError::TooShort)
+    } else if length > MAX_PASSWORD_LENGTH {
+        Err(NewPasswordError::TooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// Hash `password` with Argon2id, and return the hash in PHC string format.
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = argon2()
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+/// Argon2id hash of a random password, with the same parameters as `argon2()`.
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$\
+LC+dnvPOSI/2yHBMi0DW6g$\
+gEwOfJ8uzKDH8VvRVsqNrxNRLN0wVNxs59Hey4YH/RU";
+
+/// Return Argon2id hasher with the parameters recommended by OWASP.
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params())
+}
+
+/// Current Argon2 parameters. Hashes computed with other ones are upgraded on login.
+fn params() -> Params {
+    Params::new(19456, 2, 1, None).expect("Invalid Argon2 parameters.")
+}
+
+/// Return `true` if `password_hash` wasn't computed with the current algorithm and parameters.
+/// Unparsable hashes are left to `verify_password_hash` to reject.
+fn is_outdated(password_hash: &Secret<String>) -> bool {
+    let password_hash = match PasswordHash::new(password_hash.expose_secret()) {
+        Ok(password_hash) => password_hash,
+        Err(_) => return false,
+    };
+    let current = params();
+    let same_params = Params::try_from(&password_hash)
+        .map(|p| {
+            (p.m_cost(), p.t_cost(), p.p_cost())
+                == (current.m_cost(), current.t_cost(), current.p_cost())
+        })
+        .unwrap_or(false);
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || !same_params
+}
+
+/// Return id and password hash of the user with `username`, if any.
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?
+    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+
+    Ok(row)
+}
+
+/// Check `password_candidate` against `expected_password_hash`, using the algorithm
+/// and parameters stored in the hash.
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compute_password_hash, is_outdated, validate_new_password, verify_password_hash,
+        NewPasswordError, DUMMY_PASSWORD_HASH,
+    };
+    use argon2::password_hash::SaltString;
+    use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
+    use claim::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    #[test]
+    fn password_hash_is_argon2id_in_phc_format() {
+        let hash = compute_password_hash(Secret::new("correct horse".into())).unwrap();
+        assert!(hash
+            .expose_secret()
+            .starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+    }
+
+    #[test]
+    fn only_the_hashed_password_is_verified() {
+        let hash = compute_password_hash(Secret::new("correct horse".into())).unwrap();
+
+        assert_ok!(verify_password_hash(
+            hash.clone(),
+            Secret::new("correct horse".into())
+        ));
+        assert_err!(verify_password_hash(
+            hash,
+            Secret::new("battery staple".into())
+        ));
+    }
+
+    #[test]
+    fn dummy_hash_uses_current_parameters() {
+        let dummy = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        let current = compute_password_hash(Secret::new("whatever".into())).unwrap();
+        let current = PasswordHash::new(current.expose_secret()).unwrap();
+
+        assert_eq!(dummy.algorithm, current.algorithm);
+        assert_eq!(dummy.params, current.params);
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_are_outdated() {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let weak = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(4096, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"correct horse", &salt)
+        .unwrap()
+        .to_string();
+        let current = compute_password_hash(Secret::new("correct horse".into())).unwrap();
+
+        assert!(is_outdated(&Secret::new(weak)));
+        assert!(!is_outdated(&current));
+    }
+
+    #[test]
+    fn new_password_length_is_limited() {
+        let password = |length| Secret::new("a".repeat(length));
+
+        assert_eq!(
+            validate_new_password(&password(11)),
+            Err(NewPasswordError::TooShort)
+        );
+        assert_ok!(validate_new_password(&password(12)));
+        assert_ok!(validate_new_password(&password(128)));
+        assert_eq!(
+            validate_new_password(&password(129)),
+            Err(NewPasswordError::TooLong)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Contains roles of admin users, and the permissions they grant.
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Role of an admin user.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Full access, including management of other admins.
+    Owner,
+    /// Drafts and publishes issues, and manages subscribers.
+    Editor,
+    /// Read-only access to stats and subscriber lists.
+    Viewer,
+}
+
+/// Action guarded by `require_permission`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Read subscriber lists and delivery stats.
+    ViewSubscribers,
+    /// Add, import and edit subscribers.
+    ManageSubscribers,
+    /// Draft and publish newsletter issues.
+    PublishNewsletters,
+    /// Invite, demote and remove admin users.
+    ManageUsers,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    /// Parse the role from its name, e.g. `editor`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("`{}` is not a known role.", s))
+    }
+
+    /// Return `true` if users with this role are allowed to perform `permission`.
+    pub fn permits(&self, permission: Permission) -> bool {
+        match self {
+            Role::Owner => true,
+            Role::Editor => permission != Permission::ManageUsers,
+            Role::Viewer => permission == Permission::ViewSubscribers,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Return the role of the user with `user_id`, or `None` if there is no such user.
+#[tracing::instrument(name = "Get role", skip(pool))]
+pub async fn get_role(user_id: Uuid, pool: &PgPool) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT role FROM users WHERE user_id = $1"#, user_id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to perform a query to retrieve a role.")?;
+    row.map(|row| Role::parse(&row.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+/// Check that the user is allowed to perform `permission`, and return their role.
+/// Fail with `403 FORBIDDEN` otherwise.
+///
+/// Roles are checked on every request, so that demotions take effect immediately.
+pub async fn require_permission(
+    user_id: Uuid,
+    permission: Permission,
+    pool: &PgPool,
+) -> Result<Role, actix_web::Error> {
+    match get_role(user_id, pool).await {
+        Ok(Some(role)) if role.permits(permission) => Ok(role),
+        Ok(_) => Err(actix_web::error::ErrorForbidden(format!(
+            "You are not allowed to perform this action ({:?}).",
+            permission
+        ))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+    use claim::assert_err;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert_err!(Role::parse("admin"));
+    }
+
+    #[test]
+    fn only_owners_manage_users() {
+        assert!(Role::Owner.permits(Permission::ManageUsers));
+        assert!(!Role::Editor.permits(Permission::ManageUsers));
+        assert!(!Role::Viewer.permits(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn editors_publish_and_viewers_only_read() {
+        assert!(Role::Editor.permits(Permission::PublishNewsletters));
+        assert!(Role::Editor.permits(Permission::ManageSubscribers));
+        assert!(Role::Viewer.permits(Permission::ViewSubscribers));
+        assert!(!Role::Viewer.permits(Permission::PublishNewsletters));
+        assert!(!Role::Viewer.permits(Permission::ManageSubscribers));
+    }
+}
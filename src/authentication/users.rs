@@ -0,0 +1,270 @@
+//! Contains management of admin users: invitations, role changes and removals.
+use super::password::compute_password_hash;
+use super::role::Role;
+use crate::configuration::AdminSettings;
+use crate::domain::SubscriberEmail;
+use crate::session::delete_user_sessions;
+use crate::telemetry::spawn_blocking_with_tracing;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Maximum length of a username, in characters.
+const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Admin user as listed to owners.
+pub struct StoredUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+}
+
+/// Failure of user management functions which the caller can fix.
+#[derive(thiserror::Error)]
+pub enum UserManagementError {
+    #[error("The username must be between 1 and {MAX_USERNAME_LENGTH} characters long.")]
+    InvalidUsername,
+    #[error("The username is already taken.")]
+    UsernameTaken,
+    #[error("There is no such user.")]
+    UnknownUser,
+    #[error("There must be at least one owner left.")]
+    LastOwner,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UserManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Create a user with `username`, `password` and `role`. Return the id of the new user.
+#[tracing::instrument(name = "Create user", skip(password, pool))]
+pub async fn create_user(
+    pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+) -> Result<Uuid, UserManagementError> {
+    let username = username.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(UserManagementError::InvalidUsername);
+    }
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    let user_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new user.")?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(UserManagementError::UsernameTaken);
+    }
+
+    Ok(user_id)
+}
+
+/// Create the first admin user from `admin` settings, with `owner` role, if there are no users yet.
+#[tracing::instrument(name = "Bootstrap admin user", skip_all, fields(username = %admin.username))]
+pub async fn bootstrap_admin(pool: &PgPool, admin: &AdminSettings) -> Result<(), anyhow::Error> {
+    let users = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to count users.")?;
+    if users.count > 0 {
+        return Ok(());
+    }
+
+    match create_user(pool, &admin.username, admin.password.clone(), Role::Owner).await {
+        Ok(_) => tracing::info!("Created the first admin user."),
+        // Another replica has just done the same: let the first one win.
+        Err(UserManagementError::UsernameTaken) => {}
+        Err(e) => return Err(anyhow::anyhow!(e).context("Failed to store the first admin user.")),
+    }
+
+    Ok(())
+}
+
+/// Invite a new admin with `role`. Return the temporary password to hand over to them:
+/// they are expected to change it on first login.
+#[tracing::instrument(name = "Invite user", skip(pool))]
+pub async fn invite_user(
+    pool: &PgPool,
+    username: &str,
+    role: Role,
+) -> Result<Secret<String>, UserManagementError> {
+    let password = generate_temporary_password();
+    create_user(pool, username, password.clone(), role).await?;
+    Ok(password)
+}
+
+/// Return all users, ordered by username.
+#[tracing::instrument(name = "Get users", skip(pool))]
+pub async fn get_users(pool: &PgPool) -> Result<Vec<StoredUser>, anyhow::Error> {
+    let rows = sqlx::query!(r#"SELECT user_id, username, role FROM users ORDER BY username"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to perform a query to retrieve users.")?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(StoredUser {
+                user_id: row.user_id,
+                username: row.username,
+                role: Role::parse(&row.role).map_err(anyhow::Error::msg)?,
+            })
+        })
+        .collect()
+}
+
+/// Give `role` to the user. Fail if it would leave no owner.
+#[tracing::instrument(name = "Change role", skip(pool))]
+pub async fn change_role(
+    pool: &PgPool,
+    user_id: Uuid,
+    role: Role,
+) -> Result<(), UserManagementError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if role != Role::Owner {
+        ensure_another_owner_remains(&mut transaction, user_id).await?;
+    }
+    let updated = sqlx::query!(
+        r#"UPDATE users SET role = $1 WHERE user_id = $2"#,
+        role.as_str(),
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to change user's role.")?
+    .rows_affected();
+    if updated == 0 {
+        return Err(UserManagementError::UnknownUser);
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a role.")?;
+
+    Ok(())
+}
+
+/// Return the email address of the user, `None` if they haven't given one.
+#[tracing::instrument(name = "Get user email", skip(pool))]
+pub async fn get_user_email(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<SubscriberEmail>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve user's email.")?;
+    row.email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .context("The stored email of the user is invalid.")
+}
+
+/// Store the email address of the user, or forget it if `None`.
+#[tracing::instrument(name = "Set user email", skip(pool))]
+pub async fn set_user_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    email: Option<&SubscriberEmail>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE user_id = $2"#,
+        email.map(|e| e.as_ref()),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store user's email.")?;
+
+    Ok(())
+}
+
+/// Delete the user, their API keys, saved responses and sessions. Fail if it would leave no owner.
+#[tracing::instrument(name = "Remove user", skip(pool))]
+pub async fn remove_user(pool: &PgPool, user_id: Uuid) -> Result<(), UserManagementError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    ensure_another_owner_remains(&mut transaction, user_id).await?;
+    sqlx::query!(r#"DELETE FROM api_keys WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete user's API keys.")?;
+    sqlx::query!(r#"DELETE FROM idempotency WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete user's saved responses.")?;
+    delete_user_sessions(&mut transaction, user_id)
+        .await
+        .context("Failed to delete user's sessions.")?;
+    sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete a user.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to remove a user.")?;
+
+    Ok(())
+}
+
+/// Lock all user rows until the transaction is over, so that concurrent changes can't remove
+/// the last owner. Fail with `LastOwner` if the user with `user_id` is the only owner,
+/// and `UnknownUser` if there is no such user.
+async fn ensure_another_owner_remains(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), UserManagementError> {
+    let users = sqlx::query!(r#"SELECT user_id, role FROM users FOR UPDATE"#)
+        .fetch_all(transaction)
+        .await
+        .context("Failed to lock users.")?;
+    if !users.iter().any(|user| user.user_id == user_id) {
+        return Err(UserManagementError::UnknownUser);
+    }
+    let owners_left = users
+        .iter()
+        .filter(|user| user.user_id != user_id && user.role == Role::Owner.as_str())
+        .count();
+    if owners_left == 0 {
+        return Err(UserManagementError::LastOwner);
+    }
+    Ok(())
+}
+
+/// Generate a random 20-characters-long password.
+fn generate_temporary_password() -> Secret<String> {
+    let mut rng = thread_rng();
+    Secret::new(
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(20)
+            .collect(),
+    )
+}
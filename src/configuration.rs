@@ -1,5 +1,8 @@
 //! Application configuration stuff.
-use crate::domain::SubscriberEmail;
+use crate::domain::{SubscriberEmail, SubscriberEmailError};
+use crate::email_client::{
+    EmailClient, FileTransport, PostmarkTransport, RetryPolicy, SmtpTls, SmtpTransport,
+};
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -10,6 +13,8 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub admin: AdminSettings,
+    pub delivery_worker: DeliveryWorkerSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -28,19 +33,138 @@ pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    /// Public URL of the application, used to build links sent in emails.
+    pub base_url: String,
+    /// Key signing and encrypting session cookies. Must be at least 64 bytes long.
+    pub hmac_secret: Secret<String>,
+    /// Key signing flash message cookies. Must be at least 64 bytes long.
+    pub flash_secret: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    /// Which transport delivers the emails.
+    pub kind: EmailTransportKind,
+    /// Postmark API URL.
     pub base_url: String,
     pub sender_email: String,
+    /// Postmark server token.
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub retry: EmailRetrySettings,
+    pub smtp: SmtpSettings,
+    pub file: FileSettings,
+}
+
+/// Supported ways to deliver emails.
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    Postmark,
+    Smtp,
+    File,
+}
+
+/// SMTP server settings, used when `kind` is `smtp`.
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// `AUTH` is skipped unless both username and password are set.
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+}
+
+/// Outbox directory settings, used when `kind` is `file`.
+#[derive(serde::Deserialize, Clone)]
+pub struct FileSettings {
+    /// Directory to write `.eml` files into. Created if it doesn't exist.
+    pub directory: String,
+}
+
+/// How failed requests to the email API are retried.
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailRetrySettings {
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub jitter: bool,
+}
+
+impl EmailRetrySettings {
+    /// Return retry policy for `EmailClient`.
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.max_delay_milliseconds),
+            jitter: self.jitter,
+        }
+    }
+}
+
+/// Credentials of the first admin user, created with `owner` role on startup if there are no users yet.
+#[derive(serde::Deserialize, Clone)]
+pub struct AdminSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Settings of the background worker delivering newsletter issues.
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliveryWorkerSettings {
+    pub poll_interval_milliseconds: u64,
+    pub batch_size: i64,
+    pub max_retries: i16,
+}
+
+impl DeliveryWorkerSettings {
+    /// Return the time worker sleeps when there are no pending deliveries.
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
 }
 
 impl EmailClientSettings {
+    /// Build `EmailClient` using the transport selected by `kind`.
+    /// Panic if sender email, SMTP settings or outbox directory are invalid.
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let retry_policy = self.retry.policy();
+        match self.kind {
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+                retry_policy,
+            ),
+            EmailTransportKind::Smtp => {
+                let credentials = match (self.smtp.username, self.smtp.password) {
+                    (Some(username), Some(password)) => Some((username, password)),
+                    _ => None,
+                };
+                let transport = SmtpTransport::new(
+                    &self.smtp.host,
+                    self.smtp.port,
+                    self.smtp.tls,
+                    credentials,
+                    timeout,
+                )
+                .expect("Invalid SMTP settings.");
+                EmailClient::new(sender_email, transport, retry_policy)
+            }
+            EmailTransportKind::File => {
+                let transport = FileTransport::new(&self.file.directory)
+                    .expect("Failed to create outbox directory.");
+                EmailClient::new(sender_email, transport, retry_policy)
+            }
+        }
+    }
+
     /// Return "Single sender email" authorised on Postmark.
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
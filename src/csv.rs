@@ -0,0 +1,238 @@
+//! Contains `CsvReader`, splitting CSV data into records as it arrives, chunk by chunk,
+//! and `write_record` to produce CSV data.
+use csv_core::ReadRecordResult;
+use std::str::Utf8Error;
+
+/// Maximum size of the fields of a record, in bytes, separators excluded.
+pub const MAX_RECORD_SIZE: usize = 64 * 1024;
+/// Maximum number of fields of a record.
+pub const MAX_RECORD_FIELDS: usize = 1024;
+
+/// Fields of a CSV record, or the reason why they can't be read.
+pub type CsvRecord = Result<Vec<String>, CsvRecordError>;
+
+/// Reason why the fields of a record can't be read.
+#[derive(thiserror::Error, Debug)]
+pub enum CsvRecordError {
+    #[error("The row is not valid UTF-8.")]
+    InvalidUtf8(#[from] Utf8Error),
+    #[error(
+        "The row is longer than {} bytes, or has more than {} fields.",
+        MAX_RECORD_SIZE,
+        MAX_RECORD_FIELDS
+    )]
+    TooLong,
+}
+
+/// Incremental CSV parser, which doesn't need the whole file in memory.
+/// Quoted fields may contain separators, line breaks and `""` escaped quotes.
+///
+/// Records over `MAX_RECORD_SIZE` or `MAX_RECORD_FIELDS` are read as `CsvRecordError::TooLong`,
+/// without keeping them: a file without line breaks takes no more memory than a short record.
+pub struct CsvReader {
+    core: csv_core::Reader,
+    /// Bytes of the fields of the record being read, without separators.
+    fields: Vec<u8>,
+    fields_len: usize,
+    /// End of each field of the record being read, in `fields`.
+    ends: Vec<usize>,
+    ends_len: usize,
+    /// Whether the record being read is too long: its bytes are discarded as they arrive.
+    too_long: bool,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        Self {
+            core: csv_core::Reader::new(),
+            fields: vec![0; 1024],
+            fields_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+            too_long: false,
+        }
+    }
+}
+
+impl CsvReader {
+    /// Parse `chunk` and return the records it completes.
+    /// The beginning of a record which isn't complete yet is kept until the next chunk.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<CsvRecord> {
+        // `csv_core` takes empty input as the end of data.
+        if chunk.is_empty() {
+            return vec![];
+        }
+        self.read(chunk)
+    }
+
+    /// Return the last record, if the data doesn't end with a line break.
+    pub fn finish(&mut self) -> Vec<CsvRecord> {
+        self.read(&[])
+    }
+
+    fn read(&mut self, mut input: &[u8]) -> Vec<CsvRecord> {
+        let mut records = vec![];
+        loop {
+            let (result, nin, nout, nend) = self.core.read_record(
+                input,
+                &mut self.fields[self.fields_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[nin..];
+            self.fields_len += nout;
+            self.ends_len += nend;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                ReadRecordResult::OutputFull if self.fields.len() >= MAX_RECORD_SIZE => {
+                    self.too_long = true;
+                    self.fields_len = 0;
+                }
+                ReadRecordResult::OutputFull => self.fields.resize(self.fields.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull if self.ends.len() >= MAX_RECORD_FIELDS => {
+                    self.too_long = true;
+                    self.ends_len = 0;
+                }
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+
+    /// Return the record which has just been read, and make room for the next one.
+    fn take_record(&mut self) -> CsvRecord {
+        if self.too_long {
+            self.too_long = false;
+            self.fields_len = 0;
+            self.ends_len = 0;
+            return Err(CsvRecordError::TooLong);
+        }
+        let mut start = 0;
+        let record = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = std::str::from_utf8(&self.fields[start..end])
+                    .map(str::to_owned)
+                    .map_err(CsvRecordError::from);
+                start = end;
+                field
+            })
+            .collect();
+        self.fields_len = 0;
+        self.ends_len = 0;
+        record
+    }
+}
+
+/// Append a record with `fields` to `buffer`, followed by a line break.
+/// Fields with separators, quotes or line breaks are quoted.
+pub fn write_record(buffer: &mut Vec<u8>, fields: &[&str]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            buffer.push(b',');
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            buffer.push(b'"');
+            buffer.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+            buffer.push(b'"');
+        } else {
+            buffer.extend_from_slice(field.as_bytes());
+        }
+    }
+    buffer.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_record, CsvReader, CsvRecord, CsvRecordError, MAX_RECORD_SIZE};
+    use claim::assert_err;
+
+    /// Feed `data` to a new reader `chunk_size` bytes at a time, and return all records.
+    fn read_in_chunks(data: &[u8], chunk_size: usize) -> Vec<CsvRecord> {
+        let mut reader = CsvReader::default();
+        let mut records: Vec<_> = data
+            .chunks(chunk_size)
+            .flat_map(|chunk| reader.feed(chunk))
+            .collect();
+        records.extend(reader.finish());
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_read_whole() {
+        let data =
+            "email,name\r\na@example.com,\"Doe, \"\"John\"\"\nSecond line\"\nb@example.com,Jane";
+
+        for chunk_size in [1, 2, 7, 1000] {
+            let records: Vec<_> = read_in_chunks(data.as_bytes(), chunk_size)
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+
+            assert_eq!(
+                records,
+                vec![
+                    vec!["email", "name"],
+                    vec!["a@example.com", "Doe, \"John\"\nSecond line"],
+                    vec!["b@example.com", "Jane"],
+                ],
+                "Unexpected records with {}-bytes chunks.",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn long_records_are_read_whole() {
+        let name = "n".repeat(5000);
+        let fields = vec!["x"; 100].join(",");
+        let data = format!("{}\n{}\n", name, fields);
+
+        let records = read_in_chunks(data.as_bytes(), 64);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().unwrap(), &vec![name]);
+        assert_eq!(records[1].as_ref().unwrap().len(), 100);
+    }
+
+    #[test]
+    fn records_over_the_maximum_size_are_errors() {
+        let name = "n".repeat(MAX_RECORD_SIZE + 1);
+        let data = format!(
+            "{}\n\"quoted\n{}\"\nok,short\n{}",
+            name,
+            name,
+            ",".repeat(2000)
+        );
+
+        let records = read_in_chunks(data.as_bytes(), 1000);
+
+        assert_eq!(records.len(), 4);
+        assert!(matches!(records[0], Err(CsvRecordError::TooLong)));
+        assert!(matches!(records[1], Err(CsvRecordError::TooLong)));
+        assert_eq!(records[2].as_ref().unwrap(), &vec!["ok", "short"]);
+        assert!(matches!(records[3], Err(CsvRecordError::TooLong)));
+    }
+
+    #[test]
+    fn records_with_invalid_utf8_are_errors() {
+        let records = read_in_chunks(b"caf\xe9,latin-1\nok,utf-8\n", 1000);
+
+        assert_err!(&records[0]);
+        assert_eq!(records[1].as_ref().unwrap(), &vec!["ok", "utf-8"]);
+    }
+
+    #[test]
+    fn written_records_are_read_back() {
+        let fields = ["plain", "with, comma", "with \"quotes\"", "two\nlines", ""];
+        let mut buffer = vec![];
+        write_record(&mut buffer, &fields);
+
+        assert_eq!(
+            String::from_utf8(buffer.clone()).unwrap(),
+            "plain,\"with, comma\",\"with \"\"quotes\"\"\",\"two\nlines\",\n"
+        );
+        let records = read_in_chunks(&buffer, 1000);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].as_ref().unwrap(), &fields);
+    }
+}
@@ -0,0 +1,20 @@
+/// How often a subscriber gets issues, stored in the `delivery_frequency` column
+/// of `subscriptions`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryFrequency {
+    /// Every issue, as soon as it is published.
+    #[default]
+    Immediately,
+    /// Issues published during the week, all together on Monday.
+    Weekly,
+}
+
+impl DeliveryFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Immediately => "immediately",
+            Self::Weekly => "weekly",
+        }
+    }
+}
@@ -1,7 +1,11 @@
+mod delivery_frequency;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
 
-pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use delivery_frequency::DeliveryFrequency;
+pub use new_subscriber::{NewSubscriber, NewSubscriberError};
+pub use subscriber_email::{SubscriberEmail, SubscriberEmailError};
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
+pub use subscription_status::SubscriptionStatus;
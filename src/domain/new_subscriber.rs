@@ -1,6 +1,34 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{SubscriberEmail, SubscriberEmailError, SubscriberName, SubscriberNameError};
 
+#[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
 }
+
+/// Validation failure of one of `NewSubscriber` fields.
+#[derive(thiserror::Error, Debug)]
+pub enum NewSubscriberError {
+    #[error("Invalid subscriber name.")]
+    InvalidName(#[from] SubscriberNameError),
+    #[error("Invalid subscriber email.")]
+    InvalidEmail(#[from] SubscriberEmailError),
+}
+
+impl NewSubscriberError {
+    /// Name of the field which failed validation.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::InvalidName(_) => "name",
+            Self::InvalidEmail(_) => "email",
+        }
+    }
+
+    /// Why the field failed validation.
+    pub fn reason(&self) -> String {
+        match self {
+            Self::InvalidName(e) => e.to_string(),
+            Self::InvalidEmail(e) => e.to_string(),
+        }
+    }
+}
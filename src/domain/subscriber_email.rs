@@ -1,16 +1,27 @@
 //! Contains domain-specific `SubscriberEmail` type, and corresponding unit tests.
 use validator::validate_email;
 
-#[derive(Debug)]
+/// Reason why a string is not a valid `SubscriberEmail`.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberEmailError {
+    #[error("must not be empty")]
+    Empty,
+    #[error("is not a valid email address")]
+    InvalidFormat,
+}
+
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     /// Use `validator::validate_email` to ensure `s` contains valid email.
-    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
+    pub fn parse(s: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        if s.trim().is_empty() {
+            Err(SubscriberEmailError::Empty)
+        } else if validate_email(&s) {
             Ok(Self(s))
         } else {
-            Err(format!("{} is not valid subscriber email,", s))
+            Err(SubscriberEmailError::InvalidFormat)
         }
     }
 }
@@ -27,7 +38,7 @@ impl AsRef<str> for SubscriberEmail {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
+    use super::{SubscriberEmail, SubscriberEmailError};
     use claim::assert_err;
     // We are importing the `SafeEmail` faker!
     // We also need the `Fake` trait to get access to the `.fake` method on `SafeEmail`
@@ -54,13 +65,19 @@ mod tests {
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse(email)),
+            SubscriberEmailError::Empty
+        );
     }
 
     #[test]
     fn email_missing_at_symbol_is_rejected() {
         let email = "domain.com".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_eq!(
+            assert_err!(SubscriberEmail::parse(email)),
+            SubscriberEmailError::InvalidFormat
+        );
     }
 
     #[test]
@@ -1,6 +1,17 @@
 //! Contains domain-specific `SubscriberName` type, and corresponding unit tests.
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Reason why a string is not a valid `SubscriberName`.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberNameError {
+    #[error("must not be empty or whitespace only")]
+    Empty,
+    #[error("must be at most 256 characters long")]
+    TooLong,
+    #[error(r#"must not contain any of the characters / ( ) " < > \ {{ }}"#)]
+    ForbiddenCharacters,
+}
+
 /// Represents validated subscriber name.
 #[derive(Debug)]
 pub struct SubscriberName(String);
@@ -8,7 +19,7 @@ pub struct SubscriberName(String);
 impl SubscriberName {
     /// Return an instance of `SubscriberName` if the input satisfies all validation constraints.
     /// Return an `Err` otherwise.
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
         let is_empty_or_whitespace = s.trim().is_empty();
 
         // A grapheme is defined by the Unicode standard as a "user-perceived"
@@ -25,8 +36,12 @@ impl SubscriberName {
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
         let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
+        if is_empty_or_whitespace {
+            Err(SubscriberNameError::Empty)
+        } else if is_too_long {
+            Err(SubscriberNameError::TooLong)
+        } else if contains_forbidden_characters {
+            Err(SubscriberNameError::ForbiddenCharacters)
         } else {
             Ok(Self(s))
         }
@@ -45,7 +60,7 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{SubscriberName, SubscriberNameError};
     use claim::{assert_err, assert_ok};
 
     #[test]
@@ -57,13 +72,19 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            assert_err!(SubscriberName::parse(name)),
+            SubscriberNameError::TooLong
+        );
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            assert_err!(SubscriberName::parse(name)),
+            SubscriberNameError::Empty
+        );
     }
 
     #[test]
@@ -75,7 +96,10 @@ mod tests {
     fn names_containing_an_invalid_character_are_rejected() {
         for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
             let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+            assert_eq!(
+                assert_err!(SubscriberName::parse(name)),
+                SubscriberNameError::ForbiddenCharacters
+            );
         }
     }
     #[test]
@@ -0,0 +1,30 @@
+/// Stage of a subscription, stored in the `status` column of `subscriptions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    /// Signed up, but hasn't clicked the confirmation link yet.
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    /// Parse a value of the `status` column. Return `None` if it isn't one of `as_str` values.
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            Self::PendingConfirmation,
+            Self::Confirmed,
+            Self::Unsubscribed,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == s)
+    }
+}
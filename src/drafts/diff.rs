@@ -0,0 +1,54 @@
+//! Contains line diffs between two versions of a draft field.
+use similar::{ChangeTag, TextDiff};
+
+/// Line of a diff, without its line break.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Unchanged(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Return the lines turning `old` into `new`, in order.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| {
+            let line = change.value().trim_end_matches(['\r', '\n']).to_owned();
+            match change.tag() {
+                ChangeTag::Equal => DiffLine::Unchanged(line),
+                ChangeTag::Delete => DiffLine::Removed(line),
+                ChangeTag::Insert => DiffLine::Added(line),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, DiffLine};
+
+    #[test]
+    fn changed_lines_are_removed_then_added() {
+        let diff = diff_lines("Hello\nworld\nBye\n", "Hello\nthere\nBye");
+
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Unchanged("Hello".into()),
+                DiffLine::Removed("world".into()),
+                DiffLine::Removed("Bye".into()),
+                DiffLine::Added("there".into()),
+                DiffLine::Added("Bye".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_texts_have_no_changes() {
+        let diff = diff_lines("Hello\r\nworld", "Hello\r\nworld");
+
+        assert!(diff.iter().all(|l| matches!(l, DiffLine::Unchanged(_))));
+        assert_eq!(diff.len(), 2);
+    }
+}
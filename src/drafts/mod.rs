@@ -0,0 +1,265 @@
+//! Contains drafts of newsletter issues, and their edit history.
+//!
+//! Every save of a draft stores a new numbered revision, and revisions are never edited, so that
+//! any two of them can be compared. Autosaves are the exception: an autosave replaces the latest
+//! revision if it is an autosave by the same author, so that typing doesn't bury the saves which
+//! matter under hundreds of revisions.
+mod diff;
+
+pub use diff::{diff_lines, DiffLine};
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Content of a draft, as edited.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct DraftContent {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Draft as listed, with its latest revision.
+pub struct DraftSummary {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub revision: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Revision of a draft, without its content.
+pub struct Revision {
+    pub revision: i32,
+    /// `None` if the author was removed.
+    pub author: Option<String>,
+    pub autosaved: bool,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// Create a draft with `content` as its first revision. Return the id of the new draft.
+#[tracing::instrument(name = "Create draft", skip(pool, content))]
+pub async fn create_draft(
+    pool: &PgPool,
+    author_id: Uuid,
+    content: &DraftContent,
+) -> Result<Uuid, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (draft_id, created_at, updated_at)
+        VALUES ($1, now(), now())
+        "#,
+        draft_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    insert_revision(&mut transaction, draft_id, 1, author_id, content, false).await?;
+    transaction.commit().await?;
+
+    Ok(draft_id)
+}
+
+/// Store `content` as the latest revision of the draft, as described in the module docs.
+/// Saving the same content again stores nothing, but a manual save keeps an autosaved
+/// revision for good. Return the number of the latest revision, or `None` if there is no
+/// such draft.
+#[tracing::instrument(name = "Save draft", skip(pool, content))]
+pub async fn save_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    author_id: Uuid,
+    content: &DraftContent,
+    autosave: bool,
+) -> Result<Option<i32>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    // Concurrent saves of the same draft wait for each other, so that revision numbers
+    // don't collide.
+    let draft = sqlx::query!(
+        r#"SELECT draft_id FROM newsletter_drafts WHERE draft_id = $1 FOR UPDATE"#,
+        draft_id
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    if draft.is_none() {
+        return Ok(None);
+    }
+    let latest = sqlx::query!(
+        r#"
+        SELECT revision, title, text_content, html_content, author_id, autosaved
+        FROM newsletter_draft_revisions
+        WHERE draft_id = $1
+        ORDER BY revision DESC
+        LIMIT 1
+        "#,
+        draft_id
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let latest_content = DraftContent {
+        title: latest.title,
+        text_content: latest.text_content,
+        html_content: latest.html_content,
+    };
+
+    let revision = if &latest_content == content {
+        if !autosave && latest.autosaved {
+            sqlx::query!(
+                r#"
+                UPDATE newsletter_draft_revisions
+                SET autosaved = false
+                WHERE draft_id = $1 AND revision = $2
+                "#,
+                draft_id,
+                latest.revision
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        latest.revision
+    } else if autosave && latest.autosaved && latest.author_id == Some(author_id) {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_draft_revisions
+            SET title = $3, text_content = $4, html_content = $5, saved_at = now()
+            WHERE draft_id = $1 AND revision = $2
+            "#,
+            draft_id,
+            latest.revision,
+            content.title,
+            content.text_content,
+            content.html_content
+        )
+        .execute(&mut transaction)
+        .await?;
+        latest.revision
+    } else {
+        let revision = latest.revision + 1;
+        insert_revision(
+            &mut transaction,
+            draft_id,
+            revision,
+            author_id,
+            content,
+            autosave,
+        )
+        .await?;
+        revision
+    };
+    sqlx::query!(
+        r#"UPDATE newsletter_drafts SET updated_at = now() WHERE draft_id = $1"#,
+        draft_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(Some(revision))
+}
+
+/// Store revision number `revision` of the draft.
+async fn insert_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    revision: i32,
+    author_id: Uuid,
+    content: &DraftContent,
+    autosaved: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_draft_revisions (
+            draft_id,
+            revision,
+            title,
+            text_content,
+            html_content,
+            author_id,
+            autosaved,
+            saved_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        "#,
+        draft_id,
+        revision,
+        content.title,
+        content.text_content,
+        content.html_content,
+        author_id,
+        autosaved
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Return all drafts, the last updated first.
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+pub async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT DISTINCT ON (d.updated_at, d.draft_id)
+            d.draft_id, r.title, r.revision, d.updated_at
+        FROM newsletter_drafts d
+        JOIN newsletter_draft_revisions r ON r.draft_id = d.draft_id
+        ORDER BY d.updated_at DESC, d.draft_id, r.revision DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Return the content of revision number `revision` of the draft, the latest one if `None`,
+/// with its number. Return `None` if there is no such draft or revision.
+#[tracing::instrument(name = "Get draft content", skip(pool))]
+pub async fn get_draft_content(
+    pool: &PgPool,
+    draft_id: Uuid,
+    revision: Option<i32>,
+) -> Result<Option<(i32, DraftContent)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT revision, title, text_content, html_content
+        FROM newsletter_draft_revisions
+        WHERE draft_id = $1 AND ($2::int IS NULL OR revision = $2)
+        ORDER BY revision DESC
+        LIMIT 1
+        "#,
+        draft_id,
+        revision
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| {
+        (
+            r.revision,
+            DraftContent {
+                title: r.title,
+                text_content: r.text_content,
+                html_content: r.html_content,
+            },
+        )
+    }))
+}
+
+/// Return the revisions of the draft, the latest first.
+#[tracing::instrument(name = "Get draft revisions", skip(pool))]
+pub async fn get_revisions(pool: &PgPool, draft_id: Uuid) -> Result<Vec<Revision>, sqlx::Error> {
+    sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT r.revision, u.username AS "author?", r.autosaved, r.saved_at
+        FROM newsletter_draft_revisions r
+        LEFT JOIN users u ON u.user_id = r.author_id
+        WHERE r.draft_id = $1
+        ORDER BY r.revision DESC
+        "#,
+        draft_id
+    )
+    .fetch_all(pool)
+    .await
+}
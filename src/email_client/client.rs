@@ -0,0 +1,324 @@
+//! Contains `EmailClient`, used by the rest of the application to send emails.
+use super::{EmailMessage, EmailTransport, TransportError};
+use crate::domain::SubscriberEmail;
+use rand::Rng;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{field, Instrument};
+
+/// Builds emails and hands them over to the configured `EmailTransport`,
+/// retrying failed attempts according to its `RetryPolicy`.
+/// Clones share the same transport.
+#[derive(Clone)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Arc<dyn EmailTransport>,
+    retry_policy: RetryPolicy,
+}
+
+/// Describes how failed attempts to send an email are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every subsequent one.
+    pub base_delay: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Duration,
+    /// Pick a random delay between zero and the computed backoff ("full jitter"), so that
+    /// clients failing at the same time don't retry at the same time.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Return the delay to wait for after the failed attempt number `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+        if self.jitter {
+            rand::thread_rng().gen_range(Duration::ZERO..=backoff)
+        } else {
+            backoff
+        }
+    }
+}
+
+impl EmailClient {
+    pub fn new(
+        sender: SubscriberEmail,
+        transport: impl EmailTransport + 'static,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            sender,
+            transport: Arc::new(transport),
+            retry_policy,
+        }
+    }
+
+    /// Send an email to `recipient`.
+    ///
+    /// Every message carries RFC 8058 one-click unsubscribe headers pointing to
+    /// `unsubscribe_url`: bulk mail without them is rejected by Gmail and Yahoo.
+    ///
+    /// Retryable failures are retried according to the client's `RetryPolicy`, waiting for
+    /// as long as the server asks (e.g. with `Retry-After` header), if it does. If the server asks
+    /// us to wait for longer than the policy's `max_delay`, we give up instead of stalling the caller.
+    #[tracing::instrument(
+        name = "Send an email",
+        skip_all,
+        fields(attempts = field::Empty)
+    )]
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        unsubscribe_url: &str,
+    ) -> Result<(), anyhow::Error> {
+        let message = self.message(
+            &recipient,
+            subject,
+            html_body,
+            text_body,
+            Some(unsubscribe_url),
+        );
+        self.with_retries(|| self.transport.send(&message))
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Send an email to `recipient`, like `send_email` but without unsubscribe headers.
+    ///
+    /// For mail to admins about the app itself (e.g. test issues), which is not sent to
+    /// a list: mail providers would offer to unsubscribe from it, and a one-click POST
+    /// to an admin page fails.
+    #[tracing::instrument(
+        name = "Send an admin email",
+        skip_all,
+        fields(attempts = field::Empty)
+    )]
+    pub async fn send_admin_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Result<(), anyhow::Error> {
+        let message = self.message(&recipient, subject, html_body, text_body, None);
+        self.with_retries(|| self.transport.send(&message))
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Send the same email to all `recipients`, each with their own unsubscribe link.
+    ///
+    /// Recipients are split into chunks of the transport's `max_batch_size`, one request each.
+    /// A chunk rejected as a whole is retried like a single email in `send_email`; messages
+    /// rejected individually are not retried here. If the chunk still fails, its recipients are
+    /// all reported as retryable: the failure is about the request (e.g. a bad server token or
+    /// a network outage), not about them, so only rejections of their own message are permanent.
+    ///
+    /// Return recipients the email wasn't delivered to, so that the caller can retry only those.
+    #[tracing::instrument(
+        name = "Send a batch of emails",
+        skip_all,
+        fields(recipients = recipients.len(), failed = field::Empty)
+    )]
+    pub async fn send_batch(
+        &self,
+        recipients: &[BatchRecipient],
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Vec<FailedRecipient> {
+        let mut failed = vec![];
+        for chunk in recipients.chunks(self.transport.max_batch_size().max(1)) {
+            let messages: Vec<_> = chunk
+                .iter()
+                .map(|r| {
+                    self.message(
+                        &r.email,
+                        subject,
+                        html_body,
+                        text_body,
+                        Some(&r.unsubscribe_url),
+                    )
+                })
+                .collect();
+            match self
+                .with_retries(|| self.transport.send_batch(&messages))
+                .await
+            {
+                Ok(results) => {
+                    for (recipient, result) in chunk.iter().zip(results) {
+                        if let Err(failure) = result {
+                            failed.push(FailedRecipient::new(recipient, &failure));
+                        }
+                    }
+                }
+                Err(failure) => {
+                    tracing::error!(
+                        error.cause_chain = ?failure.error,
+                        "Failed to send a batch of {} emails.",
+                        chunk.len()
+                    );
+                    failed.extend(chunk.iter().map(|r| FailedRecipient {
+                        retryable: true,
+                        ..FailedRecipient::new(r, &failure)
+                    }));
+                }
+            }
+        }
+        tracing::Span::current().record("failed", failed.len());
+        failed
+    }
+
+    /// Build a message from the client's sender, with one-click unsubscribe headers pointing
+    /// to `unsubscribe_url`, if any.
+    fn message(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        unsubscribe_url: Option<&str>,
+    ) -> EmailMessage {
+        let headers = match unsubscribe_url {
+            Some(unsubscribe_url) => vec![
+                (
+                    "List-Unsubscribe".to_owned(),
+                    format!("<{}>", unsubscribe_url),
+                ),
+                (
+                    "List-Unsubscribe-Post".to_owned(),
+                    "List-Unsubscribe=One-Click".to_owned(),
+                ),
+            ],
+            None => vec![],
+        };
+        EmailMessage {
+            from: self.sender.as_ref().to_owned(),
+            to: recipient.as_ref().to_owned(),
+            subject: subject.to_owned(),
+            html_body: html_body.to_owned(),
+            text_body: text_body.to_owned(),
+            headers,
+        }
+    }
+
+    /// Run `attempt_fn` until it succeeds, fails permanently, or the `RetryPolicy` gives up.
+    /// Record the number of attempts in the current span.
+    async fn with_retries<T, F, Fut>(&self, mut attempt_fn: F) -> Result<T, TransportError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            tracing::Span::current().record("attempts", attempt);
+            let span =
+                tracing::info_span!("Email delivery attempt", attempt, status = field::Empty);
+            let failure = match attempt_fn().instrument(span).await {
+                Ok(outcome) => return Ok(outcome),
+                Err(failure) => failure,
+            };
+
+            if !failure.retryable || attempt >= self.retry_policy.max_attempts {
+                return Err(failure);
+            }
+            let delay = match failure.retry_after {
+                Some(delay) if delay > self.retry_policy.max_delay => {
+                    tracing::warn!(
+                        "The email server asked to retry in {:?}, which is too long. Giving up.",
+                        delay
+                    );
+                    return Err(failure);
+                }
+                Some(delay) => delay,
+                None => self.retry_policy.backoff(attempt),
+            };
+            tracing::warn!(
+                error.cause_chain = ?failure.error,
+                "Failed to send an email. Retrying in {:?}.",
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Recipient of an email sent with `EmailClient::send_batch`.
+pub struct BatchRecipient {
+    pub email: SubscriberEmail,
+    pub unsubscribe_url: String,
+}
+
+/// Recipient `EmailClient::send_batch` failed to deliver the email to.
+#[derive(Debug)]
+pub struct FailedRecipient {
+    pub email: SubscriberEmail,
+    /// Description of the failure, including its causes.
+    pub reason: String,
+    /// Whether the email is likely to be delivered if we try again later.
+    pub retryable: bool,
+}
+
+impl FailedRecipient {
+    fn new(recipient: &BatchRecipient, failure: &TransportError) -> Self {
+        Self {
+            email: recipient.email.clone(),
+            reason: format!("{:#}", failure.error),
+            retryable: failure.retryable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    /// Get a retry policy with short delays.
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(1500),
+            jitter: false,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_attempt_up_to_max_delay() {
+        let policy = retry_policy();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(20));
+        assert_eq!(policy.backoff(3), Duration::from_millis(40));
+        assert_eq!(policy.backoff(100), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn backoff_with_jitter_never_exceeds_backoff_without_it() {
+        let policy = RetryPolicy {
+            jitter: true,
+            ..retry_policy()
+        };
+
+        for attempt in 1..10 {
+            let backoff = RetryPolicy {
+                jitter: false,
+                ..policy.clone()
+            }
+            .backoff(attempt);
+            assert!(policy.backoff(attempt) <= backoff);
+        }
+    }
+}
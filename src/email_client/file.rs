@@ -0,0 +1,72 @@
+//! Contains `FileTransport`, writing emails as `.eml` files instead of delivering them.
+use super::{EmailMessage, EmailTransport, TransportError};
+use chrono::Utc;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes each message as an RFC 5322 `.eml` file into a directory.
+/// Handy for local development: the files can be opened with any mail client.
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl FileTransport {
+    /// Create the transport, and the `directory` if it doesn't exist yet.
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, std::io::Error> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    /// Write the message into a new file. File names start with a timestamp,
+    /// so that listing the directory shows messages in the order they were sent.
+    async fn send(&self, message: &EmailMessage) -> Result<(), TransportError> {
+        let email = message.build().map_err(TransportError::permanent)?;
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            Uuid::new_v4()
+        );
+
+        tokio::fs::write(self.directory.join(file_name), email.formatted())
+            .await
+            .map_err(TransportError::transient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileTransport;
+    use crate::email_client::{EmailMessage, EmailTransport};
+    use claim::assert_ok;
+
+    #[tokio::test]
+    async fn messages_are_written_as_eml_files() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let transport = FileTransport::new(&directory).unwrap();
+        let message = EmailMessage {
+            from: "sender@example.com".into(),
+            to: "recipient@example.com".into(),
+            subject: "Welcome!".into(),
+            html_body: "<p>Hello!</p>".into(),
+            text_body: "Hello!".into(),
+            headers: vec![],
+        };
+
+        assert_ok!(transport.send(&message).await);
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("To: recipient@example.com"));
+        assert!(content.contains("Subject: Welcome!"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+//! Contains `InMemoryTransport`, keeping emails in an outbox which tests can query.
+use super::{EmailMessage, EmailTransport, TransportError};
+use std::sync::{Arc, Mutex};
+
+/// Keeps sent messages in memory instead of delivering them.
+///
+/// Clones share the same outbox: keep one clone to inspect what the application
+/// has sent through the other.
+#[derive(Clone, Default)]
+pub struct InMemoryTransport {
+    outbox: Arc<Mutex<Outbox>>,
+}
+
+#[derive(Default)]
+struct Outbox {
+    messages: Vec<EmailMessage>,
+    /// Number of upcoming attempts which fail with a transient error.
+    failures_to_inject: usize,
+    /// Number of upcoming attempts which fail with a permanent error.
+    rejections_to_inject: usize,
+}
+
+impl InMemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return all messages sent so far, in the order they were sent.
+    pub fn messages(&self) -> Vec<EmailMessage> {
+        self.outbox.lock().unwrap().messages.clone()
+    }
+
+    /// Make the next `n` attempts fail with a transient error, to simulate an outage.
+    pub fn fail_next_attempts(&self, n: usize) {
+        self.outbox.lock().unwrap().failures_to_inject = n;
+    }
+
+    /// Make the next `n` attempts fail with a permanent error, to simulate rejected recipients.
+    pub fn reject_next_attempts(&self, n: usize) {
+        self.outbox.lock().unwrap().rejections_to_inject = n;
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for InMemoryTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<(), TransportError> {
+        let mut outbox = self.outbox.lock().unwrap();
+        if outbox.failures_to_inject > 0 {
+            outbox.failures_to_inject -= 1;
+            return Err(TransportError::transient(anyhow::anyhow!(
+                "Simulated email transport failure."
+            )));
+        }
+        if outbox.rejections_to_inject > 0 {
+            outbox.rejections_to_inject -= 1;
+            return Err(TransportError::permanent(anyhow::anyhow!(
+                "Simulated email rejection."
+            )));
+        }
+        outbox.messages.push(message.clone());
+        Ok(())
+    }
+}
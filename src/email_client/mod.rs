@@ -0,0 +1,13 @@
+mod client;
+mod file;
+mod memory;
+mod postmark;
+mod smtp;
+mod transport;
+
+pub use client::{BatchRecipient, EmailClient, FailedRecipient, RetryPolicy};
+pub use file::FileTransport;
+pub use memory::InMemoryTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::{SmtpTls, SmtpTransport};
+pub use transport::{EmailMessage, EmailTransport, TransportError};
@@ -0,0 +1,682 @@
+//! Contains `PostmarkTransport`, delivering emails through Postmark's `/email`
+//! and `/email/batch` JSON APIs.
+use super::{EmailMessage, EmailTransport, TransportError};
+use chrono::Utc;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Response, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// Maximum number of messages Postmark accepts in a single batch request.
+const MAX_BATCH_SIZE: usize = 500;
+
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        // NB: As a rule of thumb: every time you are performing an IO operation, *always* set a timeout!
+        // If the server takes longer than the timeout to respond, we should fail and return an error.
+        // Here we add a default timeout on the `Client` itself, which applies to all outgoing requests.
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    /// Make a single request to Postmark's API.
+    /// Timeouts, connection errors, `429 TOO MANY REQUESTS` and `5xx` responses are worth retrying.
+    async fn send(&self, message: &EmailMessage) -> Result<(), TransportError> {
+        self.post("email", &SendEmailRequest::from(message))
+            .await
+            .map(|_| ())
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    /// Make a single request to Postmark's batch API.
+    /// The request as a whole fails and is retried like in `send`; otherwise each message
+    /// gets its own result, built from `ErrorCode` and `Message` of the matching response entry.
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), TransportError>>, TransportError> {
+        let request_body: Vec<_> = messages.iter().map(SendEmailRequest::from).collect();
+        let response = self.post("email/batch", &request_body).await?;
+        let entries: Vec<BatchResponseEntry> = response.json().await.map_err(|e| {
+            TransportError::permanent(
+                anyhow::Error::new(e).context("Failed to parse Postmark batch response."),
+            )
+        })?;
+        if entries.len() != messages.len() {
+            return Err(TransportError::permanent(anyhow::anyhow!(
+                "Postmark returned {} results for a batch of {} messages.",
+                entries.len(),
+                messages.len()
+            )));
+        }
+
+        Ok(entries
+            .into_iter()
+            .map(BatchResponseEntry::into_result)
+            .collect())
+    }
+}
+
+impl PostmarkTransport {
+    /// Post `body` as JSON to `path` of Postmark's API, and check the response status.
+    async fn post(
+        &self,
+        path: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<Response, TransportError> {
+        let url = format!("{}/{}", self.base_url, path);
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| {
+                // Timeouts, refused and reset connections: the server may be back later.
+                if e.is_timeout() || e.is_connect() || e.is_request() {
+                    TransportError::transient(e)
+                } else {
+                    TransportError::permanent(e)
+                }
+            })?;
+
+        let status = response.status();
+        tracing::Span::current().record("status", status.as_u16());
+        let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        response.error_for_status().map_err(|e| TransportError {
+            error: e.into(),
+            retryable,
+            retry_after,
+        })
+    }
+}
+
+/// Parse `Retry-After` header value, given either as a number of seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means we can retry right away
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    headers: Vec<EmailHeader<'a>>,
+}
+
+impl<'a> From<&'a EmailMessage> for SendEmailRequest<'a> {
+    fn from(message: &'a EmailMessage) -> Self {
+        Self {
+            from: &message.from,
+            to: &message.to,
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect(),
+        }
+    }
+}
+
+/// Custom email header, as expected by Postmark's `Headers` field.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+/// Result of sending a single message of a batch.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResponseEntry {
+    /// `0` on success, Postmark's API error code otherwise.
+    error_code: i64,
+    message: String,
+}
+
+impl BatchResponseEntry {
+    /// Postmark's API error codes describe problems with the message itself (e.g. inactive
+    /// recipient), so they are not worth retrying.
+    fn into_result(self) -> Result<(), TransportError> {
+        match self.error_code {
+            0 => Ok(()),
+            code => Err(TransportError::permanent(anyhow::anyhow!(
+                "Postmark rejected the message with error code {}: {}",
+                code,
+                self.message
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_retry_after, PostmarkTransport};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{BatchRecipient, EmailClient, RetryPolicy};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use std::time::Duration;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body as a JSON value
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                // Check that all the mandatory fields are populated
+                // without inspecting the field values
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+                    && body.get("Headers").is_some()
+            } else {
+                // If parsing failed, do not match the request
+                false
+            }
+        }
+    }
+
+    // Helper functions
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random unsubscribe URL
+    fn unsubscribe_url() -> String {
+        format!(
+            "https://example.com/unsubscribe?token={}",
+            Faker.fake::<String>()
+        )
+    }
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+    /// Get a retry policy allowing 3 attempts, with short delays.
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(1500),
+            jitter: false,
+        }
+    }
+    /// Get a test instance of `EmailClient`.
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(
+                base_url,
+                Secret::new(Faker.fake()),
+                std::time::Duration::from_millis(200),
+            ),
+            retry_policy(),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        // We do not copy in all the matchers we have in the other test.
+        // The purpose of this test is not to assert on the request we are sending out!
+        // We add the bare minimum needed to trigger the path we want to test in `send_email`.
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500)) // Not a 200 anymore!
+            .expect(3) // Every attempt allowed by the retry policy fails
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)); // 3 minutes!
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(3) // Timeouts are retried, too
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        // Spin up the server on a background thread, ready to listen for incoming requests.
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200)) // respond with `200 OK`
+            .expect(1) // NB: Not the same as `expect()` on `Result`
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_includes_one_click_unsubscribe_headers() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                "https://example.com/unsubscribe?token=abc",
+            )
+            .await;
+
+        // Assert
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe?token=abc>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_recovers_after_a_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_if_the_server_returns_400() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_honours_retry_after_header() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = std::time::Instant::now();
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_if_retry_after_exceeds_max_delay() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &unsubscribe_url(),
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[test]
+    fn retry_after_is_parsed_from_seconds_and_http_dates() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        // A date in the past
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    /// Respond to batch requests with one entry per message, all successful
+    /// except for the messages sent to `rejected`.
+    struct BatchResponder {
+        rejected: Option<String>,
+    }
+
+    impl wiremock::Respond for BatchResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let entries: Vec<_> = messages
+                .iter()
+                .map(|m| match &self.rejected {
+                    Some(rejected) if m["To"] == rejected.as_str() => serde_json::json!({
+                        "ErrorCode": 406,
+                        "Message": "You tried to send to a recipient that has been marked as inactive."
+                    }),
+                    _ => serde_json::json!({"ErrorCode": 0, "Message": "OK"}),
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(entries)
+        }
+    }
+
+    /// Generate `n` batch recipients with random emails.
+    fn recipients(n: usize) -> Vec<BatchRecipient> {
+        (0..n)
+            .map(|_| BatchRecipient {
+                email: email(),
+                unsubscribe_url: unsubscribe_url(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_recipients_into_chunks_of_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(BatchResponder { rejected: None })
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let failed = email_client(mock_server.uri())
+            .send_batch(&recipients(501), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(failed.is_empty());
+        let requests = mock_server.received_requests().await.unwrap();
+        let sizes: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![500, 1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_returns_recipients_rejected_by_postmark() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let recipients = recipients(3);
+
+        Mock::given(any())
+            .respond_with(BatchResponder {
+                rejected: Some(recipients[1].email.as_ref().to_owned()),
+            })
+            .expect(1) // Messages rejected individually are not retried
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let failed = email_client(mock_server.uri())
+            .send_batch(&recipients, &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].email.as_ref(), recipients[1].email.as_ref());
+        assert!(failed[0].reason.contains("406"));
+        assert!(!failed[0].retryable);
+    }
+
+    #[tokio::test]
+    async fn send_batch_returns_all_recipients_if_the_batch_fails() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3) // The whole batch is retried
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let failed = email_client(mock_server.uri())
+            .send_batch(&recipients(3), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(failed.len(), 3);
+        assert!(failed.iter().all(|f| f.retryable));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_recipients_of_a_rejected_batch_as_retryable() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1) // Not retried by the client
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let failed = email_client(mock_server.uri())
+            .send_batch(&recipients(3), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(failed.len(), 3);
+        assert!(failed.iter().all(|f| f.retryable));
+    }
+}
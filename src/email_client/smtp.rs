@@ -0,0 +1,72 @@
+//! Contains `SmtpTransport`, delivering emails to an SMTP server (e.g. a MailHog-style sink).
+use super::{EmailMessage, EmailTransport, TransportError};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+/// How the connection to the SMTP server is secured.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text connection. Only suitable for local development sinks!
+    None,
+    /// Upgrade a plain text connection with `STARTTLS` (usually on port 587).
+    StartTls,
+    /// TLS from the start (usually on port 465).
+    Tls,
+}
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    /// Build SMTP transport for the server at `host:port`.
+    /// Authenticate with `AUTH` if `credentials` are provided.
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        timeout: std::time::Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        // NB: Just like for HTTP requests, *always* set a timeout on IO operations.
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    /// Send a single message to the SMTP server.
+    /// Only permanent (`5xx`) replies and messages the client can't send are not worth retrying:
+    /// timeouts, connection errors and transient (`4xx`) replies are.
+    async fn send(&self, message: &EmailMessage) -> Result<(), TransportError> {
+        let email = message.build().map_err(TransportError::permanent)?;
+
+        self.mailer.send(email).await.map(|_| ()).map_err(|e| {
+            if let Some(code) = e.status() {
+                tracing::Span::current().record("status", tracing::field::display(code));
+            }
+            if e.is_permanent() || e.is_client() {
+                TransportError::permanent(e)
+            } else {
+                TransportError::transient(e)
+            }
+        })
+    }
+}
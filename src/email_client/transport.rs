@@ -0,0 +1,138 @@
+//! Contains `EmailTransport` trait, implemented by every way we have to deliver emails.
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::MultiPart;
+use lettre::Message;
+use std::time::Duration;
+
+/// Email ready to be handed over to a transport.
+#[derive(Clone, Debug)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    /// Custom headers, as `(name, value)` pairs.
+    pub headers: Vec<(String, String)>,
+}
+
+impl EmailMessage {
+    /// Build RFC 5322 message with both plain text and HTML alternatives.
+    pub fn build(&self) -> Result<Message, anyhow::Error> {
+        let mut builder = Message::builder()
+            .from(self.from.parse()?)
+            .to(self.to.parse()?)
+            .subject(&self.subject);
+        for (name, value) in &self.headers {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii(name.clone())?,
+                value.clone(),
+            ));
+        }
+        let email = builder.multipart(MultiPart::alternative_plain_html(
+            self.text_body.clone(),
+            self.html_body.clone(),
+        ))?;
+
+        Ok(email)
+    }
+}
+
+/// Delivers emails to the outside world (an HTTP API, an SMTP server, ...).
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// Make a single attempt to deliver `message`.
+    /// Retries are up to `EmailClient`, guided by the returned `TransportError`.
+    async fn send(&self, message: &EmailMessage) -> Result<(), TransportError>;
+
+    /// Maximum number of messages accepted by a single `send_batch` call.
+    fn max_batch_size(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Make a single attempt to deliver all `messages`.
+    ///
+    /// Return `Err` if the batch as a whole was rejected, or one result per message,
+    /// in the same order, otherwise.
+    /// By default, messages are sent one by one with `send`.
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), TransportError>>, TransportError> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send(message).await);
+        }
+        Ok(results)
+    }
+}
+
+/// Failed attempt to deliver an email.
+#[derive(Debug)]
+pub struct TransportError {
+    pub error: anyhow::Error,
+    /// Whether the same message is likely to be delivered if we try again later.
+    pub retryable: bool,
+    /// Delay requested by the remote server before the next attempt, if any.
+    pub retry_after: Option<Duration>,
+}
+
+impl TransportError {
+    /// Failure which won't go away if we try again.
+    pub fn permanent(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    /// Failure which may go away if we try again.
+    pub fn transient(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            retryable: true,
+            retry_after: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailMessage;
+    use claim::assert_err;
+
+    /// Get a test message with a custom header.
+    fn message() -> EmailMessage {
+        EmailMessage {
+            from: "sender@example.com".into(),
+            to: "recipient@example.com".into(),
+            subject: "Welcome!".into(),
+            html_body: "<p>Hello!</p>".into(),
+            text_body: "Hello!".into(),
+            headers: vec![(
+                "List-Unsubscribe".into(),
+                "<https://example.com/unsubscribe>".into(),
+            )],
+        }
+    }
+
+    #[test]
+    fn message_includes_custom_headers_and_both_bodies() {
+        let formatted = String::from_utf8(message().build().unwrap().formatted()).unwrap();
+
+        assert!(formatted.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("<p>Hello!</p>"));
+    }
+
+    #[test]
+    fn message_with_invalid_recipient_is_rejected() {
+        let message = EmailMessage {
+            to: "definitely-not-an-email".into(),
+            ..message()
+        };
+
+        assert_err!(message.build());
+    }
+}
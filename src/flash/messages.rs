@@ -0,0 +1,101 @@
+//! Contains `FlashMessages`, one-shot messages shown after a redirect.
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use std::cell::RefCell;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Severity of a flash message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Error,
+}
+
+/// Message shown once, on the page the client is redirected to.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FlashMessage {
+    pub(super) level: Level,
+    pub(super) content: String,
+}
+
+impl FlashMessage {
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+/// Flash messages of the current request, shared with `FlashMessagesMiddleware`.
+#[derive(Default)]
+pub(super) struct FlashState {
+    /// Messages set by a previous request.
+    pub incoming: Vec<FlashMessage>,
+    /// Messages set by the current request, for the next one.
+    pub outgoing: Vec<FlashMessage>,
+    /// Whether `incoming` messages were read, and can be discarded.
+    pub read: bool,
+}
+
+/// Reads messages set by the previous request, and sets messages for the next one.
+///
+/// Messages are carried by a signed cookie: clients can see them, but can't forge them.
+/// They are discarded once read. Requires `FlashMessagesMiddleware`.
+#[derive(Clone)]
+pub struct FlashMessages(pub(super) Rc<RefCell<FlashState>>);
+
+impl FlashMessages {
+    /// Return messages set by the previous request, discarding them: they are shown once.
+    pub fn incoming(&self) -> Vec<FlashMessage> {
+        let mut state = self.0.borrow_mut();
+        state.read = true;
+        state.incoming.clone()
+    }
+
+    /// Return messages set by the previous request as HTML paragraphs, discarding them.
+    pub fn incoming_html(&self) -> String {
+        self.incoming()
+            .iter()
+            .map(|m| format!("<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())))
+            .collect()
+    }
+
+    /// Show `content` to the client on the next request, as information.
+    pub fn info(&self, content: impl Into<String>) {
+        self.push(Level::Info, content.into());
+    }
+
+    /// Show `content` to the client on the next request, as an error.
+    pub fn error(&self, content: impl Into<String>) {
+        self.push(Level::Error, content.into());
+    }
+
+    fn push(&self, level: Level, content: String) {
+        self.0
+            .borrow_mut()
+            .outgoing
+            .push(FlashMessage { level, content });
+    }
+}
+
+impl FromRequest for FlashMessages {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<FlashMessages, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let messages = req
+            .extensions()
+            .get::<FlashMessages>()
+            .cloned()
+            .ok_or_else(|| {
+                actix_web::error::ErrorInternalServerError(
+                    "`FlashMessagesMiddleware` is not registered.",
+                )
+            });
+        ready(messages)
+    }
+}
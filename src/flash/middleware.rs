@@ -0,0 +1,168 @@
+//! Contains `FlashMessagesMiddleware`, carrying flash messages in a signed cookie.
+use super::messages::{FlashMessage, FlashMessages, FlashState};
+use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, CookieJar, Key, SameSite};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpMessage;
+use std::cell::RefCell;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+const COOKIE_NAME: &str = "_flash";
+
+/// Makes `FlashMessages` available to handlers.
+///
+/// Messages are stored in a cookie signed with `key` (HMAC-SHA256), so that clients can't
+/// forge them. Tampered cookies are ignored, and removed.
+pub struct FlashMessagesMiddleware {
+    key: Key,
+}
+
+impl FlashMessagesMiddleware {
+    pub fn new(key: Key) -> Self {
+        Self { key }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for FlashMessagesMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = FlashMessagesService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(FlashMessagesService {
+            service: Rc::new(service),
+            key: self.key.clone(),
+        }))
+    }
+}
+
+/// Service wrapped by `FlashMessagesMiddleware`.
+pub struct FlashMessagesService<S> {
+    service: Rc<S>,
+    key: Key,
+}
+
+impl<S, B> Service<ServiceRequest> for FlashMessagesService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let cookie = req.cookie(COOKIE_NAME);
+        let incoming = cookie
+            .clone()
+            .map(|cookie| verify(cookie, &self.key))
+            .unwrap_or_default();
+        let state = Rc::new(RefCell::new(FlashState {
+            incoming,
+            ..FlashState::default()
+        }));
+        req.extensions_mut().insert(FlashMessages(state.clone()));
+
+        let service = self.service.clone();
+        let key = self.key.clone();
+        Box::pin(async move {
+            let mut response = service.call(req).await?;
+            let state = state.borrow();
+            if !state.outgoing.is_empty() {
+                let cookie = sign(&state.outgoing, &key)?;
+                response.response_mut().add_cookie(&cookie)?;
+            } else if cookie.is_some() && (state.read || state.incoming.is_empty()) {
+                // Shown once, or tampered with: either way, it's of no use anymore.
+                let removal = Cookie::build(COOKIE_NAME, "").path("/").finish();
+                response.response_mut().add_removal_cookie(&removal)?;
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Return messages carried by `cookie`, or none if its signature or content are invalid.
+fn verify(cookie: Cookie<'static>, key: &Key) -> Vec<FlashMessage> {
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    let verified = match jar.signed(key).get(COOKIE_NAME) {
+        Some(verified) => verified,
+        None => {
+            tracing::warn!("Ignoring flash messages cookie with an invalid signature.");
+            return vec![];
+        }
+    };
+    serde_json::from_str(verified.value()).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "Ignoring flash messages cookie with invalid content.");
+        vec![]
+    })
+}
+
+/// Return the cookie carrying `messages`, signed with `key`.
+fn sign(messages: &[FlashMessage], key: &Key) -> Result<Cookie<'static>, actix_web::Error> {
+    let value = serde_json::to_string(messages)?;
+    let mut jar = CookieJar::new();
+    jar.signed_mut(key).add(
+        Cookie::build(COOKIE_NAME, value)
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .finish(),
+    );
+    Ok(jar
+        .get(COOKIE_NAME)
+        .expect("The signed cookie was just added.")
+        .clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify, FlashMessage};
+    use crate::flash::Level;
+    use actix_web::cookie::{Cookie, Key};
+
+    fn messages() -> Vec<FlashMessage> {
+        vec![FlashMessage {
+            level: Level::Error,
+            content: "Authentication failed.".into(),
+        }]
+    }
+
+    #[test]
+    fn signed_messages_are_verified() {
+        let key = Key::generate();
+
+        let cookie = sign(&messages(), &key).unwrap();
+
+        assert_eq!(verify(cookie, &key), messages());
+    }
+
+    #[test]
+    fn messages_signed_with_another_key_are_ignored() {
+        let cookie = sign(&messages(), &Key::generate()).unwrap();
+
+        assert!(verify(cookie, &Key::generate()).is_empty());
+    }
+
+    #[test]
+    fn tampered_messages_are_ignored() {
+        let key = Key::generate();
+        let cookie = sign(&messages(), &key).unwrap();
+        let tampered = cookie
+            .value()
+            .replace("Authentication failed.", "Welcome back!");
+
+        assert!(verify(Cookie::new(cookie.name().to_owned(), tampered), &key).is_empty());
+    }
+}
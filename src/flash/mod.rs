@@ -0,0 +1,5 @@
+mod messages;
+mod middleware;
+
+pub use messages::{FlashMessage, FlashMessages, Level};
+pub use middleware::FlashMessagesMiddleware;
@@ -0,0 +1,353 @@
+//! Background worker delivering newsletter issues from `issue_delivery_queue`.
+//!
+//! Tasks are dequeued with `SELECT ... FOR UPDATE SKIP LOCKED`, so that multiple replicas
+//! of the application can share the work safely: a task locked by one worker is invisible
+//! to the others until the transaction holding it is over.
+//!
+//! Before each batch, the worker also publishes scheduled issues which are due, queueing
+//! their deliveries: see `issue_scheduler`.
+use crate::configuration::DeliveryWorkerSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{BatchRecipient, EmailClient};
+use crate::issue_scheduler::publish_due_issues;
+use crate::routes::unsubscribe_link;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use tracing::Span;
+use uuid::Uuid;
+
+/// Result of a single `try_execute_task` run.
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Holds everything the worker needs to deliver issues.
+pub struct IssueDeliveryWorker {
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    settings: DeliveryWorkerSettings,
+}
+
+impl IssueDeliveryWorker {
+    pub fn new(
+        pool: PgPool,
+        email_client: EmailClient,
+        base_url: String,
+        settings: DeliveryWorkerSettings,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            base_url,
+            settings,
+        }
+    }
+
+    /// Publish due scheduled issues, and deliver pending issues, forever.
+    /// Sleep for the configured poll interval when the queue is empty,
+    /// and for a second after an unexpected error.
+    // A failure to publish scheduled issues is logged, but doesn't hold up the deliveries
+    // already queued.
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            if let Err(e) = publish_due_issues(&self.pool).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to publish due scheduled issues.",
+                );
+            }
+            match try_execute_task(
+                &self.pool,
+                &self.email_client,
+                &self.base_url,
+                self.settings.batch_size,
+                self.settings.max_retries,
+            )
+            .await
+            {
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    tokio::time::sleep(self.settings.poll_interval()).await;
+                }
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Err(_) => {
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Dequeue a batch of due tasks and try to deliver them, one `EmailClient::send_batch`
+/// call per issue.
+///
+/// A task is deleted if the email was sent, if the stored subscriber email is invalid, or if
+/// the email was rejected for good, e.g. for an inactive recipient. Otherwise, it is postponed
+/// with exponential backoff, and dropped after `max_retries` failed attempts. Only the failed
+/// tasks of a batch are retried.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    batch_size: i64,
+    max_retries: i16,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let (mut transaction, tasks) = match dequeue_tasks(pool, batch_size).await? {
+        Some(tasks) => tasks,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut tasks_by_issue: BTreeMap<Uuid, Vec<Task>> = BTreeMap::new();
+    for task in tasks {
+        tasks_by_issue
+            .entry(task.newsletter_issue_id)
+            .or_default()
+            .push(task);
+    }
+    for (newsletter_issue_id, tasks) in tasks_by_issue {
+        deliver_issue(
+            &mut transaction,
+            pool,
+            email_client,
+            base_url,
+            max_retries,
+            newsletter_issue_id,
+            tasks,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Send the issue to subscribers of `tasks`, then delete or postpone each task.
+#[tracing::instrument(
+    skip(transaction, pool, email_client, base_url, max_retries, tasks),
+    fields(n_tasks = tasks.len())
+)]
+async fn deliver_issue(
+    transaction: &mut PgTransaction,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    max_retries: i16,
+    newsletter_issue_id: Uuid,
+    tasks: Vec<Task>,
+) -> Result<(), sqlx::Error> {
+    let issue = get_issue(pool, newsletter_issue_id).await?;
+    let emails: Vec<_> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let unsubscribe_tokens = get_unsubscribe_tokens(pool, &emails).await?;
+
+    let mut recipients = vec![];
+    for task in &tasks {
+        let email = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid.",
+                );
+                continue;
+            }
+        };
+        match unsubscribe_tokens.get(&task.subscriber_email) {
+            Some(token) => recipients.push(BatchRecipient {
+                email,
+                unsubscribe_url: unsubscribe_link(base_url, token),
+            }),
+            None => tracing::warn!(
+                subscriber_email = %task.subscriber_email,
+                "Skipping a subscriber who isn't confirmed anymore.",
+            ),
+        }
+    }
+
+    let failed = email_client
+        .send_batch(
+            &recipients,
+            &issue.title,
+            &issue.html_content,
+            &issue.text_content,
+        )
+        .await;
+    let failed: HashMap<_, _> = failed.iter().map(|f| (f.email.as_ref(), f)).collect();
+
+    let mut to_delete = vec![];
+    let mut to_postpone = vec![];
+    for task in tasks {
+        match failed.get(task.subscriber_email.as_str()) {
+            None => to_delete.push(task.subscriber_email),
+            Some(failure) if !failure.retryable => {
+                tracing::error!(
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber: {}. It won't succeed \
+                    if retried.",
+                    failure.reason
+                );
+                to_delete.push(task.subscriber_email);
+            }
+            Some(failure) if task.n_retries < max_retries => {
+                tracing::warn!(
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber: {}. Will retry later.",
+                    failure.reason
+                );
+                to_postpone.push(task.subscriber_email);
+            }
+            Some(failure) => {
+                tracing::error!(
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber: {}. Giving up.",
+                    failure.reason
+                );
+                to_delete.push(task.subscriber_email);
+            }
+        }
+    }
+    delete_tasks(transaction, newsletter_issue_id, &to_delete).await?;
+    postpone_tasks(transaction, newsletter_issue_id, &to_postpone).await?;
+
+    Ok(())
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+/// Pending delivery of an issue to a single subscriber.
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+/// Lock up to `batch_size` due tasks, skipping the ones already locked by other workers.
+/// The locks are held until the returned transaction is over.
+#[tracing::instrument(skip(pool))]
+async fn dequeue_tasks(
+    pool: &PgPool,
+    batch_size: i64,
+) -> Result<Option<(PgTransaction, Vec<Task>)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let tasks = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT $1
+        "#,
+        batch_size
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+
+    if tasks.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((transaction, tasks)))
+    }
+}
+
+/// Remove tasks of the issue for `subscriber_emails` from the queue.
+#[tracing::instrument(skip_all)]
+async fn delete_tasks(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+    subscriber_emails: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        newsletter_issue_id,
+        subscriber_emails
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Make tasks of the issue for `subscriber_emails` available again after a delay,
+/// doubling with every failed attempt.
+#[tracing::instrument(skip_all)]
+async fn postpone_tasks(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+    subscriber_emails: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => 2 ^ n_retries)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        newsletter_issue_id,
+        subscriber_emails
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Newsletter issue content.
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Get issue content by its id.
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(issue)
+}
+
+/// Get unsubscribe tokens of confirmed subscribers with `subscriber_emails`, keyed by email.
+// Weekly deliveries wait for days in the queue: subscribers may unsubscribe meanwhile.
+#[tracing::instrument(skip_all)]
+async fn get_unsubscribe_tokens(
+    pool: &PgPool,
+    subscriber_emails: &[String],
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email, unsubscribe_token
+        FROM subscriptions
+        WHERE email = ANY($1) AND status = 'confirmed'
+        "#,
+        subscriber_emails
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.email, r.unsubscribe_token))
+        .collect())
+}
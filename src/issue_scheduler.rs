@@ -0,0 +1,335 @@
+//! Contains scheduling of newsletter issues, and publishing of the ones which are due.
+//!
+//! A scheduled issue is stored with `scheduled` status, and nothing in the delivery queue.
+//! `IssueDeliveryWorker` publishes it once it is due, queueing its deliveries: from then on,
+//! it can't be rescheduled or cancelled anymore.
+//!
+//! Editors pick the local date and time in an IANA time zone, e.g. Monday 08:00 in
+//! `Europe/Berlin`. Postgres resolves it to an instant, with its own time zone database,
+//! so that daylight saving time is accounted for.
+//!
+//! An issue scheduled in subscriber time zones is delivered at that local time in the time zone
+//! of each subscriber instead: 08:00 in Tokyo, then in Berlin, then in New York. It is published
+//! as soon as the earliest time zone in the world reaches the local time, and each delivery
+//! is queued for its own time.
+use crate::problem_details::ProblemDetails;
+use crate::routes::enqueue_newsletter_issue;
+use crate::time_zones::EARLIEST_TIME_ZONE;
+use crate::utils::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Time zone of schedules which don't name one.
+pub const DEFAULT_TIME_ZONE: &str = "UTC";
+
+/// When a scheduled issue is due.
+#[derive(serde::Serialize, Debug)]
+pub struct Schedule {
+    pub scheduled_for: DateTime<Utc>,
+    /// IANA time zone the editor picked the local date and time in.
+    pub time_zone: String,
+    /// Whether each subscriber gets the issue at the local date and time in their time zone.
+    pub in_subscriber_time_zones: bool,
+}
+
+/// Failure to schedule, reschedule or cancel an issue.
+#[derive(thiserror::Error)]
+pub enum ScheduleError {
+    #[error("{0} is not a valid date and time.")]
+    InvalidTime(String),
+    #[error("{0} is not a known time zone.")]
+    UnknownTimeZone(String),
+    #[error("The scheduled time is in the past.")]
+    InThePast,
+    #[error("There is no such newsletter issue.")]
+    UnknownIssue,
+    #[error("The newsletter issue is no longer scheduled: its delivery has started, or it was cancelled.")]
+    NotScheduled,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ScheduleError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScheduleError::InvalidTime(_)
+            | ScheduleError::UnknownTimeZone(_)
+            | ScheduleError::InThePast => StatusCode::BAD_REQUEST,
+            ScheduleError::UnknownIssue => StatusCode::NOT_FOUND,
+            ScheduleError::NotScheduled => StatusCode::CONFLICT,
+            ScheduleError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = ProblemDetails::new(self.status_code());
+        match self {
+            ScheduleError::InvalidTime(_) | ScheduleError::InThePast => problem
+                .detail(self.to_string())
+                .invalid_param("scheduled_for", self.to_string())
+                .response(),
+            ScheduleError::UnknownTimeZone(_) => problem
+                .detail(self.to_string())
+                .invalid_param("time_zone", self.to_string())
+                .response(),
+            ScheduleError::UnknownIssue | ScheduleError::NotScheduled => {
+                problem.detail(self.to_string()).response()
+            }
+            ScheduleError::UnexpectedError(_) => problem.response(),
+        }
+    }
+}
+
+/// Parse local date and time, e.g. `2023-07-03T08:00`. Seconds are optional:
+/// `datetime-local` inputs leave them out.
+pub fn parse_local_time(local_time: &str) -> Result<NaiveDateTime, ScheduleError> {
+    let local_time = local_time.trim();
+    NaiveDateTime::parse_from_str(local_time, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(local_time, "%Y-%m-%dT%H:%M"))
+        .map_err(|_| ScheduleError::InvalidTime(local_time.to_owned()))
+}
+
+/// Return the schedule of an issue due at `local_time` in `time_zone`.
+/// Reject unknown time zones, and times which are already past.
+// Local times skipped by a daylight saving time transition are moved forward by its length,
+// and repeated ones resolve to their standard time occurrence, as Postgres does.
+#[tracing::instrument(name = "Resolve schedule", skip(pool))]
+pub async fn resolve_schedule(
+    pool: &PgPool,
+    local_time: &str,
+    time_zone: &str,
+    in_subscriber_time_zones: bool,
+) -> Result<Schedule, ScheduleError> {
+    let local_time = parse_local_time(local_time)?;
+    let row = sqlx::query!(
+        r#"
+        SELECT ($1::timestamp AT TIME ZONE name) AS "scheduled_for!"
+        FROM pg_timezone_names
+        WHERE name = $2
+        "#,
+        local_time,
+        time_zone
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to resolve the scheduled time.")?;
+    let scheduled_for = match row {
+        Some(row) => row.scheduled_for,
+        None => return Err(ScheduleError::UnknownTimeZone(time_zone.to_owned())),
+    };
+    if scheduled_for <= Utc::now() {
+        return Err(ScheduleError::InThePast);
+    }
+
+    Ok(Schedule {
+        scheduled_for,
+        time_zone: time_zone.to_owned(),
+        in_subscriber_time_zones,
+    })
+}
+
+/// Issue waiting for its scheduled time.
+pub struct ScheduledIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    /// Local date and time the issue is due at in `time_zone`, e.g. `2023-07-03T08:00`.
+    pub local_time: String,
+    pub time_zone: String,
+    pub in_subscriber_time_zones: bool,
+}
+
+/// Return issues waiting for their scheduled time, the next due first.
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+pub async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            to_char(
+                scheduled_for AT TIME ZONE scheduled_time_zone,
+                'YYYY-MM-DD"T"HH24:MI'
+            ) AS "local_time!",
+            scheduled_time_zone AS "time_zone!",
+            in_subscriber_time_zones
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY scheduled_for
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Move the scheduled issue with `newsletter_issue_id` to `schedule`.
+#[tracing::instrument(name = "Reschedule issue", skip(pool))]
+pub async fn reschedule_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    schedule: &Schedule,
+) -> Result<(), ScheduleError> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2, scheduled_time_zone = $3, in_subscriber_time_zones = $4
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id,
+        schedule.scheduled_for,
+        schedule.time_zone,
+        schedule.in_subscriber_time_zones
+    )
+    .execute(pool)
+    .await
+    .context("Failed to reschedule the issue.")?;
+    if result.rows_affected() == 0 {
+        return Err(not_scheduled(pool, newsletter_issue_id).await);
+    }
+
+    Ok(())
+}
+
+/// Cancel the scheduled issue with `newsletter_issue_id`. It is kept, but never published.
+#[tracing::instrument(name = "Cancel issue", skip(pool))]
+pub async fn cancel_issue(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<(), ScheduleError> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to cancel the issue.")?;
+    if result.rows_affected() == 0 {
+        return Err(not_scheduled(pool, newsletter_issue_id).await);
+    }
+
+    Ok(())
+}
+
+/// Return why the issue with `newsletter_issue_id` couldn't be updated as a scheduled issue.
+async fn not_scheduled(pool: &PgPool, newsletter_issue_id: Uuid) -> ScheduleError {
+    let exists = sqlx::query!(
+        r#"SELECT 1 AS "exists" FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the issue.");
+    match exists {
+        Ok(Some(_)) => ScheduleError::NotScheduled,
+        Ok(None) => ScheduleError::UnknownIssue,
+        Err(e) => e.into(),
+    }
+}
+
+/// Publish every scheduled issue which is due, queueing its deliveries to the subscribers
+/// of its lists. Issues scheduled in subscriber time zones are due as soon as the local time
+/// is reached in `EARLIEST_TIME_ZONE`.
+///
+/// Issues are locked with `SELECT ... FOR UPDATE SKIP LOCKED`, one at a time: replicas
+/// of the application share the work, and rescheduling or cancelling an issue being published
+/// waits for the outcome.
+#[tracing::instrument(skip_all, err)]
+pub async fn publish_due_issues(pool: &PgPool) -> Result<(), sqlx::Error> {
+    loop {
+        let mut transaction = pool.begin().await?;
+        let issue = sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE
+                status = 'scheduled' AND
+                CASE
+                    WHEN in_subscriber_time_zones THEN
+                        (scheduled_for AT TIME ZONE scheduled_time_zone) AT TIME ZONE $1
+                    ELSE scheduled_for
+                END <= now()
+            ORDER BY scheduled_for
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+            "#,
+            EARLIEST_TIME_ZONE
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        let newsletter_issue_id = match issue {
+            Some(issue) => issue.newsletter_issue_id,
+            None => return Ok(()),
+        };
+
+        let list_ids: Vec<Uuid> = sqlx::query!(
+            r#"SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issue_id = $1"#,
+            newsletter_issue_id
+        )
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|r| r.list_id)
+        .collect();
+        let report =
+            enqueue_newsletter_issue(&mut transaction, newsletter_issue_id, &list_ids).await?;
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'published', published_at = now()
+            WHERE newsletter_issue_id = $1
+            "#,
+            newsletter_issue_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        tracing::info!(
+            %newsletter_issue_id,
+            queued = report.queued,
+            skipped = report.skipped.len(),
+            "Published a scheduled newsletter issue."
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_local_time;
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn local_times_are_parsed_with_or_without_seconds() {
+        let expected = NaiveDate::from_ymd_opt(2023, 7, 3)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+
+        assert_ok_eq!(parse_local_time("2023-07-03T08:00"), expected);
+        assert_ok_eq!(parse_local_time(" 2023-07-03T08:00:00 "), expected);
+    }
+
+    #[test]
+    fn invalid_local_times_are_rejected() {
+        for local_time in [
+            "",
+            "2023-07-03",
+            "2023-07-03T25:00",
+            "2023-07-03T08:00+02:00",
+        ] {
+            assert_err!(parse_local_time(local_time));
+        }
+    }
+}
@@ -1,7 +1,22 @@
+pub mod authentication;
 pub mod configuration;
+pub mod csv;
 pub mod database;
 pub mod domain;
+pub mod drafts;
 pub mod email_client;
+pub mod flash;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod lists;
+pub mod problem_details;
 pub mod routes;
+pub mod session;
 pub mod startup;
+pub mod subscriber_data;
+pub mod subscriber_export;
+pub mod subscriber_import;
 pub mod telemetry;
+pub mod time_zones;
+pub mod utils;
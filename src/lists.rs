@@ -0,0 +1,147 @@
+//! Contains the lists subscribers choose from, and issues are published to.
+//!
+//! Lists are referred to by their slug in forms and JSON bodies, e.g. `weekly-digest`.
+//! They are managed with migrations, not from the admin pages.
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// List row.
+#[derive(serde::Serialize, Debug)]
+pub struct List {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+/// Slug which doesn't name any list.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("{0} is not a known list.")]
+pub struct UnknownListError(pub String);
+
+/// Return all lists, ordered by name.
+#[tracing::instrument(name = "Get lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<List>, sqlx::Error> {
+    sqlx::query_as!(
+        List,
+        r#"SELECT list_id, slug, name FROM lists ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Return ids of `lists` named by `slugs`, without duplicates.
+pub fn select_lists(lists: &[List], slugs: &[String]) -> Result<Vec<Uuid>, UnknownListError> {
+    let mut list_ids = vec![];
+    for slug in slugs {
+        let list = lists
+            .iter()
+            .find(|l| &l.slug == slug)
+            .ok_or_else(|| UnknownListError(slug.clone()))?;
+        if !list_ids.contains(&list.list_id) {
+            list_ids.push(list.list_id);
+        }
+    }
+    Ok(list_ids)
+}
+
+/// Return ids of `lists` named by `slugs`, or of all `lists` if `slugs` is empty.
+// Clients which don't know about lists keep getting everything.
+pub fn select_lists_or_all(
+    lists: &[List],
+    slugs: &[String],
+) -> Result<Vec<Uuid>, UnknownListError> {
+    if slugs.is_empty() {
+        Ok(lists.iter().map(|l| l.list_id).collect())
+    } else {
+        select_lists(lists, slugs)
+    }
+}
+
+/// Return ids of the lists the subscriber with `subscriber_id` is subscribed to.
+#[tracing::instrument(name = "Get subscriber lists", skip(pool))]
+pub async fn get_subscriber_lists(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT list_id FROM subscription_lists WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
+
+/// Subscribe the subscriber with `subscriber_id` to exactly `list_ids`, replacing
+/// their previous choice.
+#[tracing::instrument(name = "Set subscriber lists", skip(transaction))]
+pub async fn set_subscriber_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_lists WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_lists (subscriber_id, list_id)
+        SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select_lists, select_lists_or_all, List, UnknownListError};
+    use claim::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    fn lists() -> Vec<List> {
+        ["weekly-digest", "release-notes", "events"]
+            .into_iter()
+            .map(|slug| List {
+                list_id: Uuid::new_v4(),
+                slug: slug.into(),
+                name: slug.into(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lists_are_selected_by_slug_without_duplicates() {
+        let lists = lists();
+        let slugs = vec!["events".into(), "weekly-digest".into(), "events".into()];
+
+        let list_ids = assert_ok!(select_lists(&lists, &slugs));
+
+        assert_eq!(list_ids, vec![lists[2].list_id, lists[0].list_id]);
+    }
+
+    #[test]
+    fn unknown_slugs_are_rejected() {
+        let slugs = vec!["events".into(), "gossip".into()];
+
+        let e = assert_err!(select_lists(&lists(), &slugs));
+
+        assert_eq!(e, UnknownListError("gossip".into()));
+    }
+
+    #[test]
+    fn no_slugs_select_no_list_or_all_lists() {
+        let lists = lists();
+
+        assert!(assert_ok!(select_lists(&lists, &[])).is_empty());
+        assert_eq!(assert_ok!(select_lists_or_all(&lists, &[])).len(), 3);
+    }
+}
@@ -1,17 +1,83 @@
+use clap::{Parser, Subcommand};
 use newsletter::configuration::get_configuration;
-use newsletter::startup::Application;
+use newsletter::startup::{get_connection_pool, Application};
+use newsletter::subscriber_export::{export_subscribers_to, ExportFormat};
+use newsletter::subscriber_import::import_subscribers_from_file;
 use newsletter::telemetry::{get_subscriber, init_subscriber};
+use std::path::PathBuf;
+
+/// Newsletter delivery service.
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server and the issue delivery worker. This is the default.
+    Serve,
+    /// Import subscribers from a CSV file with `email` and `name` columns,
+    /// and print the report as JSON.
+    ImportSubscribers {
+        path: PathBuf,
+        /// Check the file and report what would be imported, without storing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export all subscribers, ordered by signup time.
+    ExportSubscribers {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// File to write the export to, instead of the standard output.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let subscriber = get_subscriber("newspaper".into(), "info".into(), std::io::stdout);
-    init_subscriber(subscriber);
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let subscriber = get_subscriber("newspaper".into(), "info".into(), std::io::stdout);
+            init_subscriber(subscriber);
+
+            // Panic if we can't read config file
+            let configuration = get_configuration().expect("Failed to read config file.");
+
+            let application = Application::build(configuration).await?;
+            application.run_until_stopped().await?;
+        }
+        Command::ImportSubscribers { path, dry_run } => {
+            // Keep `stdout` for the report.
+            let subscriber = get_subscriber("newspaper".into(), "info".into(), std::io::stderr);
+            init_subscriber(subscriber);
+
+            let configuration = get_configuration().expect("Failed to read config file.");
+            let pool = get_connection_pool(&configuration.database);
+
+            let report = import_subscribers_from_file(&pool, &path, dry_run).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::ExportSubscribers { format, output } => {
+            // Keep `stdout` for the export.
+            let subscriber = get_subscriber("newspaper".into(), "info".into(), std::io::stderr);
+            init_subscriber(subscriber);
 
-    // Panic if we can't read config file
-    let configuration = get_configuration().expect("Failed to read config file.");
+            let configuration = get_configuration().expect("Failed to read config file.");
+            let pool = get_connection_pool(&configuration.database);
 
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+            match output {
+                Some(path) => {
+                    let file = tokio::fs::File::create(&path).await?;
+                    export_subscribers_to(pool, format, file).await?;
+                }
+                None => export_subscribers_to(pool, format, tokio::io::stdout()).await?,
+            }
+        }
+    }
 
     Ok(())
 }
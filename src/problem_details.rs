@@ -0,0 +1,119 @@
+//! Contains `ProblemDetails`, an RFC 7807 JSON body describing why a request failed.
+use actix_web::error::{InternalError, QueryPayloadError, UrlencodedError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+
+/// RFC 7807 "problem details" object, returned as `application/problem+json`.
+#[derive(serde::Serialize, Debug)]
+pub struct ProblemDetails {
+    /// We don't document problem types (yet), so it's always `about:blank`,
+    /// and `title` is the reason phrase of `status`.
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    /// Request fields which failed validation, and why.
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    invalid_params: Vec<InvalidParam>,
+}
+
+/// Request field which failed validation.
+#[derive(serde::Serialize, Debug)]
+pub struct InvalidParam {
+    name: String,
+    reason: String,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown Error"),
+            status: status.as_u16(),
+            detail: None,
+            invalid_params: vec![],
+        }
+    }
+
+    /// Add human-readable explanation specific to this occurrence of the problem.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Add request field `name` to the list of fields which failed validation.
+    pub fn invalid_param(mut self, name: impl Into<String>, reason: impl Into<String>) -> Self {
+        self.invalid_params.push(InvalidParam {
+            name: name.into(),
+            reason: reason.into(),
+        });
+        self
+    }
+
+    /// Build response with the status and JSON body of the problem.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::build(StatusCode::from_u16(self.status).unwrap())
+            .content_type("application/problem+json")
+            .json(self)
+    }
+}
+
+/// Reject form bodies which can't be deserialized with a `400 BAD REQUEST` problem details
+/// response, instead of the default plain text one.
+/// Use with `web::FormConfig::error_handler`.
+pub fn form_error_handler(error: UrlencodedError, _request: &HttpRequest) -> actix_web::Error {
+    let response = ProblemDetails::new(StatusCode::BAD_REQUEST)
+        .detail(error.to_string())
+        .response();
+    InternalError::from_response(error, response).into()
+}
+
+/// Reject query strings which can't be deserialized with a `400 BAD REQUEST` problem details
+/// response, instead of the default plain text one.
+/// Use with `web::QueryConfig::error_handler`.
+pub fn query_error_handler(error: QueryPayloadError, _request: &HttpRequest) -> actix_web::Error {
+    let response = ProblemDetails::new(StatusCode::BAD_REQUEST)
+        .detail(error.to_string())
+        .response();
+    InternalError::from_response(error, response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProblemDetails;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn problem_details_are_serialized_as_rfc_7807_members() {
+        let problem = ProblemDetails::new(StatusCode::BAD_REQUEST)
+            .detail("Invalid subscriber email.")
+            .invalid_param("email", "is not a valid email address");
+
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "Invalid subscriber email.",
+                "invalid-params": [{"name": "email", "reason": "is not a valid email address"}],
+            })
+        );
+    }
+
+    #[test]
+    fn optional_members_are_omitted() {
+        let problem = ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR);
+
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Internal Server Error",
+                "status": 500,
+            })
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//!
+//! Contains `GET /admin/api_keys` endpoint handler.
+//!
+use crate::authentication::{get_api_keys, Scope, StoredApiKey, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// Return the API keys of the logged in user, and the form creating a new one.
+pub async fn api_keys(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    api_keys_page(StatusCode::OK, **user_id, &pool, None).await
+}
+
+/// Render the API keys page with `status`, and with `message_html` above the list, if any.
+/// `message_html` is not escaped.
+pub(super) async fn api_keys_page(
+    status: StatusCode,
+    user_id: uuid::Uuid,
+    pool: &PgPool,
+    message_html: Option<&str>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let keys = get_api_keys(pool, user_id).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for key in &keys {
+        write_key_row(&mut rows_html, key).map_err(e500)?;
+    }
+    let scopes = Scope::ALL.map(|s| s.as_str()).join(" ");
+    Ok(HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API keys</title>
+</head>
+<body>
+    {message_html}
+    <table>
+        <tr>
+            <th>Name</th>
+            <th>Scopes</th>
+            <th>Created</th>
+            <th>Expires</th>
+            <th>Last used</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/api_keys" method="post">
+        <label>Name
+            <input type="text" placeholder="What uses the key" name="name">
+        </label>
+        <br>
+        <label>Scopes
+            <input type="text" placeholder="{scopes}" name="scopes">
+        </label>
+        <br>
+        <label>Expires in days
+            <input type="number" min="1" placeholder="Never" name="expires_in_days">
+        </label>
+        <br>
+        <button type="submit">Create API key</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            message_html = message_html.unwrap_or_default(),
+        )))
+}
+
+/// Append the table row of `key` to `html`, with a button revoking it unless it's revoked already.
+fn write_key_row(html: &mut String, key: &StoredApiKey) -> std::fmt::Result {
+    let action = match key.revoked_at {
+        Some(revoked_at) => format!("Revoked {}", format_time(Some(revoked_at))),
+        None => format!(
+            r#"<form action="/admin/api_keys/{}/revoke" method="post"><button type="submit">Revoke</button></form>"#,
+            key.key_id
+        ),
+    };
+    write!(
+        html,
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+        htmlescape::encode_minimal(&key.name),
+        htmlescape::encode_minimal(&key.scopes.join(" ")),
+        format_time(Some(key.created_at)),
+        format_time(key.expires_at),
+        format_time(key.last_used_at),
+        action
+    )
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "Never".into())
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::api_keys;
+pub use post::{create_api_key, revoke_api_key};
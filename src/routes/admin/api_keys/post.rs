@@ -0,0 +1,109 @@
+//!
+//! Contains `POST /admin/api_keys` and `POST /admin/api_keys/{key_id}/revoke` endpoint handlers.
+//!
+use super::get::api_keys_page;
+use crate::authentication::{self, get_role, Role, Scope, UserId};
+use crate::utils::{e500, see_other};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Form data shape for `create_api_key` endpoint.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    /// Space separated scope names, e.g. `subscribers:write newsletters:publish`.
+    scopes: String,
+    /// Empty for keys which never expire.
+    #[serde(default)]
+    expires_in_days: String,
+}
+
+impl FormData {
+    /// Return the scopes of the new key, or the message explaining why the form is invalid.
+    /// Only scopes permitted by `role` of the key's creator are allowed.
+    fn parse_scopes(&self, role: Role) -> Result<Vec<Scope>, String> {
+        let scopes = self
+            .scopes
+            .split_whitespace()
+            .map(Scope::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if scopes.is_empty() {
+            return Err("The API key must have at least one scope.".into());
+        }
+        if let Some(scope) = scopes.iter().find(|s| !role.permits(s.permission())) {
+            return Err(format!(
+                "The `{}` role does not permit `{}` scope.",
+                role, scope
+            ));
+        }
+        Ok(scopes)
+    }
+
+    /// Return the validity of the new key, `None` if it never expires.
+    fn parse_validity(&self) -> Result<Option<Duration>, String> {
+        let days = self.expires_in_days.trim();
+        if days.is_empty() {
+            return Ok(None);
+        }
+        match days.parse::<u16>() {
+            Ok(days) if days > 0 => Ok(Some(Duration::days(days.into()))),
+            _ => Err("The validity must be a positive number of days.".into()),
+        }
+    }
+}
+
+/// Create an API key for the logged in user, and show it: it can't be retrieved later.
+#[tracing::instrument(skip(form, pool), fields(user_id = %*user_id))]
+pub async fn create_api_key(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    let role = get_role(user_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("The logged in user doesn't exist anymore."))?;
+    let name = form.name.trim();
+    let parsed = if name.is_empty() {
+        Err("The API key must have a name.".to_string())
+    } else {
+        form.parse_scopes(role)
+            .and_then(|scopes| Ok((scopes, form.parse_validity()?)))
+    };
+    let (scopes, validity) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            let message_html = format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message));
+            return api_keys_page(StatusCode::BAD_REQUEST, user_id, &pool, Some(&message_html))
+                .await;
+        }
+    };
+
+    let expires_at = validity.map(|validity| Utc::now() + validity);
+    let (_, key) = authentication::create_api_key(&pool, user_id, name, &scopes, expires_at)
+        .await
+        .map_err(e500)?;
+    let message_html = format!(
+        "<p><i>Your new API key is <code>{}</code>. Copy it now: it won't be shown again.</i></p>",
+        key.expose_secret()
+    );
+    api_keys_page(StatusCode::OK, user_id, &pool, Some(&message_html)).await
+}
+
+/// Revoke the API key, if the logged in user created it.
+#[tracing::instrument(skip(pool), fields(user_id = %*user_id))]
+pub async fn revoke_api_key(
+    key_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    authentication::revoke_api_key(&pool, **user_id, key_id.into_inner())
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/api_keys"))
+}
@@ -0,0 +1,79 @@
+//!
+//! Contains `GET /admin/dashboard` endpoint handler.
+//!
+use crate::authentication::{get_role, Permission, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Return the admin dashboard of the logged in user, with the actions their role permits.
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, &pool).await.map_err(e500)?;
+    let role = get_role(**user_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("The logged in user doesn't exist anymore."))?;
+    let publish_html = if role.permits(Permission::PublishNewsletters) {
+        r#"<li><a href="/admin/newsletters">Publish a newsletter issue</a></li>
+        <li><a href="/admin/drafts">Drafts</a></li>"#
+    } else {
+        ""
+    };
+    let manage_users_html = if role.permits(Permission::ManageUsers) {
+        r#"<li><a href="/admin/users">Manage admins</a></li>"#
+    } else {
+        ""
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {username}! Your role is {role}.</p>
+    <p>Available actions:</p>
+    <ol>
+        {publish_html}
+        <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/email">Email address</a></li>
+        <li><a href="/admin/api_keys">API keys</a></li>
+        {manage_users_html}
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+            username = htmlescape::encode_minimal(&username),
+        )))
+}
+
+/// Return the username of the user with `user_id`.
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve a username.")?;
+
+    Ok(row.username)
+}
@@ -0,0 +1,248 @@
+//!
+//! Contains `GET /admin/drafts`, `GET /admin/drafts/{draft_id}`
+//! and `GET /admin/drafts/{draft_id}/diff` endpoint handlers.
+//!
+use crate::authentication::{require_permission, Permission, UserId};
+use crate::drafts::{diff_lines, get_draft_content, get_drafts, get_revisions, DiffLine};
+use crate::flash::FlashMessages;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Query parameters shape for `draft_diff` endpoint.
+#[derive(serde::Deserialize)]
+pub struct DiffParameters {
+    /// Revision to compare from. None means the one before `to`.
+    from: Option<i32>,
+    /// Revision to compare to. None means the latest one.
+    to: Option<i32>,
+}
+
+/// Return the drafts, the last updated first, and the form creating a new one,
+/// with the outcome of the previous submission above it, if any. Editors and owners only.
+pub async fn drafts(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    let message_html = flash.incoming_html();
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let drafts_html = if drafts.is_empty() {
+        "<p>There are no drafts yet.</p>".to_string()
+    } else {
+        let items: String = drafts
+            .iter()
+            .map(|draft| {
+                format!(
+                    r#"<li><a href="/admin/drafts/{}">{}</a> (revision {}, updated {})</li>"#,
+                    draft.draft_id,
+                    htmlescape::encode_minimal(&draft.title),
+                    draft.revision,
+                    draft.updated_at.format("%Y-%m-%d %H:%M UTC"),
+                )
+            })
+            .collect();
+        format!("<ul>{items}</ul>")
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {message_html}
+    {drafts_html}
+    <form action="/admin/drafts" method="post">
+        <label>Title
+            <input type="text" placeholder="Enter the issue title" name="title">
+        </label>
+        <button type="submit">New draft</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// Return the form editing the latest revision of the draft, its revisions, and the actions
+/// on it, with the outcome of the previous submission above it, if any. Editors and owners only.
+// The page autosaves the form every 30 seconds if it has changed: see `autosave_draft`.
+//
+// Return `404 NOT FOUND` if there is no such draft.
+pub async fn draft_form(
+    draft_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    let draft_id = draft_id.into_inner();
+    let (revision, content) = match get_draft_content(&pool, draft_id, None)
+        .await
+        .map_err(e500)?
+    {
+        Some(latest) => latest,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let message_html = flash.incoming_html();
+    let revisions_html: String = get_revisions(&pool, draft_id)
+        .await
+        .map_err(e500)?
+        .iter()
+        .map(|r| {
+            let changes_html = if r.revision > 1 {
+                format!(
+                    r#" <a href="/admin/drafts/{draft_id}/diff?to={}">Changes</a>"#,
+                    r.revision
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                "<li>Revision {} by {}{}, {}{}</li>",
+                r.revision,
+                htmlescape::encode_minimal(r.author.as_deref().unwrap_or("a removed admin")),
+                if r.autosaved { " (autosaved)" } else { "" },
+                r.saved_at.format("%Y-%m-%d %H:%M UTC"),
+                changes_html,
+            )
+        })
+        .collect();
+    let title = htmlescape::encode_attribute(&content.title);
+    let text_content = htmlescape::encode_minimal(&content.text_content);
+    let html_content = htmlescape::encode_minimal(&content.html_content);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit Draft</title>
+</head>
+<body>
+    {message_html}
+    <p>Revision {revision}</p>
+    <form id="draft" action="/admin/drafts/{draft_id}" method="post">
+        <label>Title
+            <input type="text" placeholder="Enter the issue title" name="title" value="{title}">
+        </label>
+        <br>
+        <label>Plain text content
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50">{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50">{html_content}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/admin/drafts/{draft_id}/test" method="post">
+        <button type="submit">Send test to me</button>
+    </form>
+    <p><a href="/admin/newsletters?draft_id={draft_id}">Publish</a></p>
+    <h2>Revisions</h2>
+    <ul>{revisions_html}</ul>
+    <form action="/admin/drafts/{draft_id}/diff" method="get">
+        <label>Compare revision <input type="number" name="from" min="1" required></label>
+        <label>with revision <input type="number" name="to" min="1" required></label>
+        <button type="submit">Compare</button>
+    </form>
+    <p><a href="/admin/drafts">&lt;- Back</a></p>
+    <script>
+        const form = document.getElementById("draft");
+        let saved = new URLSearchParams(new FormData(form)).toString();
+        setInterval(() => {{
+            const body = new URLSearchParams(new FormData(form)).toString();
+            if (body === saved) {{
+                return;
+            }}
+            fetch("/admin/drafts/{draft_id}/autosave", {{
+                method: "POST",
+                headers: {{"Content-Type": "application/x-www-form-urlencoded"}},
+                body,
+            }}).then((response) => {{
+                if (response.ok) {{
+                    saved = body;
+                }}
+            }});
+        }}, 30000);
+    </script>
+</body>
+</html>"#,
+        )))
+}
+
+/// Return the line diff of each field between two revisions of the draft: by default, the changes
+/// made by the latest one. Editors and owners only.
+// Return `404 NOT FOUND` if there is no such draft or revision.
+pub async fn draft_diff(
+    draft_id: web::Path<Uuid>,
+    parameters: web::Query<DiffParameters>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    let draft_id = draft_id.into_inner();
+    let (to, new) = match get_draft_content(&pool, draft_id, parameters.to)
+        .await
+        .map_err(e500)?
+    {
+        Some(revision) => revision,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let from = parameters.from.unwrap_or((to - 1).max(1));
+    let (from, old) = match get_draft_content(&pool, draft_id, Some(from))
+        .await
+        .map_err(e500)?
+    {
+        Some(revision) => revision,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let fields_html: String = [
+        ("Title", &old.title, &new.title),
+        ("Plain text content", &old.text_content, &new.text_content),
+        ("HTML content", &old.html_content, &new.html_content),
+    ]
+    .into_iter()
+    .map(|(label, old, new)| format!("<h2>{label}</h2>\n    <pre>{}</pre>", diff_html(old, new)))
+    .collect::<Vec<_>>()
+    .join("\n    ");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Draft Changes</title>
+</head>
+<body>
+    <p>Changes from revision {from} to revision {to}</p>
+    {fields_html}
+    <p><a href="/admin/drafts/{draft_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// Render the line diff between `old` and `new`, one line per line, for a `<pre>` element.
+fn diff_html(old: &str, new: &str) -> String {
+    diff_lines(old, new)
+        .iter()
+        .map(|line| match line {
+            DiffLine::Unchanged(l) => format!("  {}", htmlescape::encode_minimal(l)),
+            DiffLine::Removed(l) => format!("<del>- {}</del>", htmlescape::encode_minimal(l)),
+            DiffLine::Added(l) => format!("<ins>+ {}</ins>", htmlescape::encode_minimal(l)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
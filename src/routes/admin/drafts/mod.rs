@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{draft_diff, draft_form, drafts};
+pub use post::{autosave_draft, create_draft, save_draft, send_test_draft};
@@ -0,0 +1,145 @@
+//!
+//! Contains `POST /admin/drafts`, `POST /admin/drafts/{draft_id}`,
+//! `POST /admin/drafts/{draft_id}/autosave` and `POST /admin/drafts/{draft_id}/test`
+//! endpoint handlers.
+//!
+use crate::authentication::{get_user_email, require_permission, Permission, UserId};
+use crate::drafts::{self, get_draft_content, DraftContent};
+use crate::email_client::EmailClient;
+use crate::flash::FlashMessages;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Form data shape for `create_draft` endpoint.
+#[derive(serde::Deserialize)]
+pub struct NewDraftFormData {
+    title: String,
+}
+
+/// Create a draft with the title from the form and no content yet, and redirect to its page.
+/// Editors and owners only.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn create_draft(
+    form: web::Form<NewDraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    require_permission(user_id, Permission::PublishNewsletters, &pool).await?;
+    let content = DraftContent {
+        title: form.0.title,
+        text_content: String::new(),
+        html_content: String::new(),
+    };
+    let draft_id = drafts::create_draft(&pool, user_id, &content)
+        .await
+        .map_err(e500)?;
+    flash.info("The draft has been created.");
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+/// Store the content from the form as a new revision of the draft, and redirect back to the
+/// draft page, which shows the outcome. Editors and owners only.
+// Return `404 NOT FOUND` if there is no such draft.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn save_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftContent>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    require_permission(user_id, Permission::PublishNewsletters, &pool).await?;
+    let draft_id = draft_id.into_inner();
+    let revision = drafts::save_draft(&pool, draft_id, user_id, &form, false)
+        .await
+        .map_err(e500)?;
+    let revision = match revision {
+        Some(revision) => revision,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    flash.info(format!(
+        "The draft has been saved as revision {}.",
+        revision
+    ));
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+/// Store the content from the form as an autosave of the draft, and return `204 NO CONTENT`.
+/// Editors and owners only.
+// Called by the draft page in the background, not by a form submission: there is no redirect
+// nor flash message.
+//
+// Return `404 NOT FOUND` if there is no such draft.
+#[tracing::instrument(skip(form, pool), fields(user_id = %*user_id))]
+pub async fn autosave_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftContent>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    require_permission(user_id, Permission::PublishNewsletters, &pool).await?;
+    let revision = drafts::save_draft(&pool, draft_id.into_inner(), user_id, &form, true)
+        .await
+        .map_err(e500)?;
+    match revision {
+        Some(_) => Ok(HttpResponse::NoContent().finish()),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Send the latest revision of the draft to the logged in user only, and redirect back
+/// to the draft page, which shows the outcome. Editors and owners only.
+// The email goes through `EmailClient` like issues do, so that it looks the same in the inbox,
+// but without unsubscribe headers: the user is not on a list.
+//
+// Return `404 NOT FOUND` if there is no such draft.
+#[tracing::instrument(skip(pool, email_client, flash), fields(user_id = %*user_id))]
+pub async fn send_test_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    require_permission(user_id, Permission::PublishNewsletters, &pool).await?;
+    let draft_id = draft_id.into_inner();
+    let draft_url = format!("/admin/drafts/{}", draft_id);
+    let content = match get_draft_content(&pool, draft_id, None)
+        .await
+        .map_err(e500)?
+    {
+        Some((_, content)) => content,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let email = match get_user_email(&pool, user_id).await.map_err(e500)? {
+        Some(email) => email,
+        None => {
+            flash.error("Add your email address on the Email address page to get test issues.");
+            return Ok(see_other(&draft_url));
+        }
+    };
+
+    let sent = email_client
+        .send_admin_email(
+            email.clone(),
+            &format!("[Test] {}", content.title),
+            &content.html_content,
+            &content.text_content,
+        )
+        .await;
+    match sent {
+        Ok(()) => flash.info(format!("A test issue has been sent to {}.", email.as_ref())),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to send a test issue.");
+            flash.error("Failed to send the test issue. Try again later.");
+        }
+    }
+    Ok(see_other(&draft_url))
+}
@@ -0,0 +1,44 @@
+//!
+//! Contains `GET /admin/email` endpoint handler.
+//!
+use crate::authentication::{get_user_email, UserId};
+use crate::flash::FlashMessages;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Return the form with the email address of the logged in user, which test sends
+/// of drafts go to, with the outcome of the previous attempt above it, if any.
+pub async fn email_form(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message_html = flash.incoming_html();
+    let email = get_user_email(&pool, **user_id).await.map_err(e500)?;
+    let email = htmlescape::encode_attribute(email.as_ref().map_or("", |e| e.as_ref()));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email Address</title>
+</head>
+<body>
+    {message_html}
+    <p>Test sends of draft issues go to this address. Leave it empty to remove it.</p>
+    <form action="/admin/email" method="post">
+        <label>Email address
+            <input type="email" placeholder="Enter your email address" name="email" value="{email}">
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
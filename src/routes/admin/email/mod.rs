@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::email_form;
+pub use post::change_email;
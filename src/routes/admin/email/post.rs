@@ -0,0 +1,44 @@
+//!
+//! Contains `POST /admin/email` endpoint handler.
+//!
+use crate::authentication::{set_user_email, UserId};
+use crate::domain::SubscriberEmail;
+use crate::flash::FlashMessages;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Form data shape for `change_email` endpoint.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Store the email address of the logged in user, or remove it if the field is empty.
+/// Redirect them back to the form, which shows the outcome.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn change_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.0.email.trim().to_owned();
+    let email = if email.is_empty() {
+        None
+    } else {
+        match SubscriberEmail::parse(email) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                flash.error(format!("The email address {}.", e));
+                return Ok(see_other("/admin/email"));
+            }
+        }
+    };
+
+    set_user_email(&pool, **user_id, email.as_ref())
+        .await
+        .map_err(e500)?;
+    flash.info("Your email address has been saved.");
+    Ok(see_other("/admin/email"))
+}
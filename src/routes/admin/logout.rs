@@ -0,0 +1,12 @@
+//!
+//! Contains `POST /admin/logout` endpoint handler.
+//!
+use crate::session::TypedSession;
+use crate::utils::see_other;
+use actix_web::HttpResponse;
+
+/// Log the user out, and redirect them to the login form.
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    session.log_out();
+    see_other("/login")
+}
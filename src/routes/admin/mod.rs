@@ -0,0 +1,19 @@
+mod api_keys;
+mod dashboard;
+mod drafts;
+mod email;
+mod logout;
+mod newsletters;
+mod password;
+mod subscribers;
+mod users;
+
+pub use api_keys::*;
+pub use dashboard::{admin_dashboard, get_username};
+pub use drafts::*;
+pub use email::*;
+pub use logout::log_out;
+pub use newsletters::*;
+pub use password::*;
+pub use subscribers::*;
+pub use users::*;
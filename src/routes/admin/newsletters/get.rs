@@ -0,0 +1,144 @@
+//!
+//! Contains `GET /admin/newsletters` endpoint handler.
+//!
+use crate::authentication::{require_permission, Permission, UserId};
+use crate::drafts::get_draft_content;
+use crate::flash::FlashMessages;
+use crate::issue_scheduler::{get_scheduled_issues, DEFAULT_TIME_ZONE};
+use crate::lists::get_lists;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Query parameters shape for `publish_newsletter_form` endpoint.
+#[derive(serde::Deserialize)]
+pub struct PublishFormParameters {
+    /// Draft to fill the form with, from its latest revision.
+    draft_id: Option<Uuid>,
+}
+
+/// Return the form publishing a newsletter issue, empty or filled with a draft, to the checked
+/// lists, all of them by default, right away or at a scheduled time, and the issues waiting
+/// for their scheduled time, with the outcome of the previous submission above it, if any.
+/// Editors and owners only.
+// Return `404 NOT FOUND` if `draft_id` names no draft.
+pub async fn publish_newsletter_form(
+    parameters: web::Query<PublishFormParameters>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    let content = match parameters.draft_id {
+        Some(draft_id) => match get_draft_content(&pool, draft_id, None)
+            .await
+            .map_err(e500)?
+        {
+            Some((_, content)) => Some(content),
+            None => return Ok(HttpResponse::NotFound().finish()),
+        },
+        None => None,
+    };
+    let title = htmlescape::encode_attribute(content.as_ref().map_or("", |c| &c.title));
+    let text_content = htmlescape::encode_minimal(content.as_ref().map_or("", |c| &c.text_content));
+    let html_content = htmlescape::encode_minimal(content.as_ref().map_or("", |c| &c.html_content));
+    let message_html = flash.incoming_html();
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_checkboxes: String = lists
+        .iter()
+        .map(|list| {
+            format!(
+                r#"<label><input type="checkbox" name="lists" value="{}" checked> {}</label><br>"#,
+                htmlescape::encode_attribute(&list.slug),
+                htmlescape::encode_minimal(&list.name),
+            )
+        })
+        .collect();
+    let scheduled_issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let scheduled_html = if scheduled_issues.is_empty() {
+        "<p>No issue is scheduled.</p>".to_string()
+    } else {
+        let items: String = scheduled_issues
+            .iter()
+            .map(|issue| {
+                let action = format!("/admin/newsletters/{}", issue.newsletter_issue_id);
+                format!(
+                    r#"<li>{title}
+            <form action="{action}/reschedule" method="post">
+                <input type="datetime-local" name="scheduled_for" value="{local_time}">
+                <input type="text" name="time_zone" value="{time_zone}">
+                <label><input type="checkbox" name="in_subscriber_time_zones" value="true"{checked}> In subscriber time zones</label>
+                <button type="submit">Reschedule</button>
+            </form>
+            <form action="{action}/cancel" method="post">
+                <button type="submit">Cancel</button>
+            </form>
+        </li>"#,
+                    title = htmlescape::encode_minimal(&issue.title),
+                    local_time = issue.local_time,
+                    time_zone = htmlescape::encode_attribute(&issue.time_zone),
+                    checked = if issue.in_subscriber_time_zones {
+                        " checked"
+                    } else {
+                        ""
+                    },
+                )
+            })
+            .collect();
+        format!("<ul>{items}</ul>")
+    };
+    // A new key for every form: resubmitting the same form doesn't publish the issue twice.
+    let idempotency_key = Uuid::new_v4();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Publish Newsletter Issue</title>
+</head>
+<body>
+    {message_html}
+    <form action="/admin/newsletters" method="post">
+        <label>Title
+            <input type="text" placeholder="Enter the issue title" name="title" value="{title}">
+        </label>
+        <br>
+        <label>Plain text content
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50">{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50">{html_content}</textarea>
+        </label>
+        <br>
+        <fieldset>
+            <legend>Lists</legend>
+            {list_checkboxes}
+        </fieldset>
+        <fieldset>
+            <legend>Schedule (leave empty to publish right away)</legend>
+            <label>Date and time
+                <input type="datetime-local" name="scheduled_for">
+            </label>
+            <label>Time zone
+                <input type="text" name="time_zone" value="{DEFAULT_TIME_ZONE}">
+            </label>
+            <label>
+                <input type="checkbox" name="in_subscriber_time_zones" value="true">
+                At this time in the time zone of each subscriber
+            </label>
+        </fieldset>
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    <h2>Scheduled issues</h2>
+    {scheduled_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
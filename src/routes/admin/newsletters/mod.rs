@@ -0,0 +1,7 @@
+mod get;
+mod post;
+
+pub use get::publish_newsletter_form;
+pub use post::{
+    cancel_newsletter_from_form, publish_newsletter_from_form, reschedule_newsletter_from_form,
+};
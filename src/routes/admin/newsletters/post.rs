@@ -0,0 +1,220 @@
+//!
+//! Contains `POST /admin/newsletters`, `POST /admin/newsletters/{newsletter_issue_id}/reschedule`
+//! and `POST /admin/newsletters/{newsletter_issue_id}/cancel` endpoint handlers.
+//!
+use crate::authentication::{require_permission, Permission, UserId};
+use crate::flash::FlashMessages;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_scheduler::{cancel_issue, reschedule_issue, resolve_schedule, ScheduleError};
+use crate::lists::{get_lists, select_lists};
+use crate::routes::{queue_newsletter_issue, schedule_newsletter_issue};
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_lab::extract::UrlEncodedForm;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Form data shape for `publish_newsletter_from_form` endpoint.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Slugs of the checked lists, one field per list.
+    #[serde(default)]
+    lists: Vec<String>,
+    /// Local date and time to publish the issue at. Empty means right away.
+    #[serde(default)]
+    scheduled_for: String,
+    /// IANA time zone of `scheduled_for`.
+    #[serde(default)]
+    time_zone: String,
+    /// Deliver the scheduled issue at `scheduled_for` in the time zone of each subscriber.
+    #[serde(default)]
+    in_subscriber_time_zones: bool,
+    idempotency_key: String,
+}
+
+/// Form data shape for `reschedule_newsletter_from_form` endpoint.
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    scheduled_for: String,
+    time_zone: String,
+    #[serde(default)]
+    in_subscriber_time_zones: bool,
+}
+
+/// Queue delivery of the issue from the form to the checked lists, or schedule it if the form
+/// has a time, and redirect back to the form, which shows the outcome. Editors and owners only.
+// The form carries an idempotency key, so that a resubmitted form gets the saved response
+// instead of publishing the issue twice.
+//
+// Unlike the JSON endpoint, the form doesn't fall back to all lists: unchecking every list
+// is a mistake, not a request to reach everyone.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn publish_newsletter_from_form(
+    form: UrlEncodedForm<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    require_permission(user_id, Permission::PublishNewsletters, &pool).await?;
+    let FormData {
+        title,
+        text_content,
+        html_content,
+        lists: list_slugs,
+        scheduled_for,
+        time_zone,
+        in_subscriber_time_zones,
+        idempotency_key,
+    } = form.into_inner();
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    if list_slugs.is_empty() {
+        flash.error("Choose at least one list to publish the issue to.");
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_ids = select_lists(&lists, &list_slugs).map_err(e400)?;
+    let schedule = if scheduled_for.trim().is_empty() {
+        None
+    } else {
+        match resolve_schedule(
+            &pool,
+            &scheduled_for,
+            time_zone.trim(),
+            in_subscriber_time_zones,
+        )
+        .await
+        {
+            Ok(schedule) => Some(schedule),
+            Err(e) => return schedule_failure(e, &flash),
+        }
+    };
+    let is_scheduled = schedule.is_some();
+    // The issue, its deliveries and the saved response must be stored together, or not at all.
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(&flash, is_scheduled);
+            return Ok(saved_response);
+        }
+    };
+    match schedule {
+        Some(schedule) => {
+            let newsletter_issue_id = schedule_newsletter_issue(
+                &mut transaction,
+                &title,
+                &text_content,
+                &html_content,
+                &list_ids,
+                &schedule,
+            )
+            .await
+            .context("Failed to schedule the newsletter issue.")
+            .map_err(e500)?;
+            tracing::info!(
+                %newsletter_issue_id,
+                scheduled_for = %schedule.scheduled_for,
+                "Scheduled the newsletter issue."
+            );
+        }
+        None => {
+            let report = queue_newsletter_issue(
+                &mut transaction,
+                &title,
+                &text_content,
+                &html_content,
+                &list_ids,
+            )
+            .await
+            .context("Failed to queue the newsletter issue for delivery.")
+            .map_err(e500)?;
+            tracing::info!(
+                newsletter_issue_id = %report.newsletter_issue_id,
+                queued = report.queued,
+                skipped = report.skipped.len(),
+                "Queued the newsletter issue for delivery."
+            );
+        }
+    }
+
+    let response = see_other("/admin/newsletters");
+    let response = save_response(transaction, &idempotency_key, user_id, response)
+        .await
+        .map_err(e500)?;
+    success_message(&flash, is_scheduled);
+    Ok(response)
+}
+
+/// Move the scheduled issue to the time and time zone from the form, and redirect back
+/// to the newsletters page, which shows the outcome. Editors and owners only.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn reschedule_newsletter_from_form(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<ScheduleFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    let outcome = match resolve_schedule(
+        &pool,
+        &form.scheduled_for,
+        form.time_zone.trim(),
+        form.in_subscriber_time_zones,
+    )
+    .await
+    {
+        Ok(schedule) => reschedule_issue(&pool, newsletter_issue_id.into_inner(), &schedule).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = outcome {
+        return schedule_failure(e, &flash);
+    }
+    flash.info("The newsletter issue has been rescheduled.");
+    Ok(see_other("/admin/newsletters"))
+}
+
+/// Cancel the scheduled issue, and redirect back to the newsletters page, which shows
+/// the outcome. Editors and owners only.
+#[tracing::instrument(skip(pool, flash), fields(user_id = %*user_id))]
+pub async fn cancel_newsletter_from_form(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::PublishNewsletters, &pool).await?;
+    if let Err(e) = cancel_issue(&pool, newsletter_issue_id.into_inner()).await {
+        return schedule_failure(e, &flash);
+    }
+    flash.info("The newsletter issue has been cancelled.");
+    Ok(see_other("/admin/newsletters"))
+}
+
+/// Show the reason the schedule was rejected on the newsletters page,
+/// or fail with `500 INTERNAL SERVER ERROR` if it wasn't the editor's mistake.
+fn schedule_failure(
+    e: ScheduleError,
+    flash: &FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let ScheduleError::UnexpectedError(_) = e {
+        return Err(e500(e));
+    }
+    flash.error(e.to_string());
+    Ok(see_other("/admin/newsletters"))
+}
+
+fn success_message(flash: &FlashMessages, is_scheduled: bool) {
+    if is_scheduled {
+        flash.info("The newsletter issue has been scheduled.");
+    } else {
+        flash.info("The newsletter issue has been queued for delivery.");
+    }
+}
@@ -0,0 +1,56 @@
+//!
+//! Contains `GET /admin/password` endpoint handler.
+//!
+use crate::authentication::{MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH};
+use crate::flash::FlashMessages;
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+
+/// Return the password change form, with the outcome of the previous attempt above it, if any.
+pub async fn change_password_form(flash: FlashMessages) -> HttpResponse {
+    let message_html = flash.incoming_html();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {message_html}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input
+                type="password"
+                placeholder="Enter current password"
+                name="current_password"
+            >
+        </label>
+        <br>
+        <label>New password
+            <input
+                type="password"
+                placeholder="Enter new password"
+                name="new_password"
+                minlength="{MIN_PASSWORD_LENGTH}"
+                maxlength="{MAX_PASSWORD_LENGTH}"
+            >
+        </label>
+        <br>
+        <label>Confirm new password
+            <input
+                type="password"
+                placeholder="Type the new password again"
+                name="new_password_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ))
+}
@@ -0,0 +1,87 @@
+//!
+//! Contains `POST /admin/password` endpoint handler.
+//!
+use crate::authentication::{
+    self, validate_credentials, validate_new_password, AuthError, Credentials, NewPasswordError,
+    UserId,
+};
+use crate::flash::FlashMessages;
+use crate::routes::admin::dashboard::get_username;
+use crate::utils::{e500, error_chain_fmt, see_other};
+use actix_web::{web, HttpResponse};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Form data shape for `change_password` endpoint.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+/// Failure of `change_password` endpoint.
+#[derive(thiserror::Error)]
+pub enum ChangePasswordError {
+    #[error("You entered two different new passwords - the field values must match.")]
+    PasswordMismatch,
+    #[error(transparent)]
+    InvalidNewPassword(#[from] NewPasswordError),
+    #[error("The current password is incorrect.")]
+    InvalidCurrentPassword(#[source] anyhow::Error),
+    #[error("Something went wrong.")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ChangePasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Change the password of the logged in user, after checking their current one.
+/// Redirect them back to the form, which shows the outcome.
+#[tracing::instrument(skip(form, pool, flash), fields(user_id = %*user_id))]
+pub async fn change_password(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    match try_change_password(form.0, &pool, *user_id.into_inner()).await {
+        Ok(()) => flash.info("Your password has been changed."),
+        Err(ChangePasswordError::UnexpectedError(e)) => return Err(e500(e)),
+        Err(e) => flash.error(e.to_string()),
+    }
+    Ok(see_other("/admin/password"))
+}
+
+/// Check the form, and store the new password.
+async fn try_change_password(
+    form: FormData,
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(), ChangePasswordError> {
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return Err(ChangePasswordError::PasswordMismatch);
+    }
+    validate_new_password(&form.new_password)?;
+
+    let username = get_username(user_id, pool).await?;
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+    validate_credentials(credentials, pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => {
+                ChangePasswordError::InvalidCurrentPassword(e.into())
+            }
+            AuthError::UnexpectedError(_) => ChangePasswordError::UnexpectedError(e.into()),
+        })?;
+
+    authentication::change_password(user_id, form.new_password, pool).await?;
+    Ok(())
+}
@@ -0,0 +1,306 @@
+//!
+//! Contains `GET /admin/subscribers` and `GET /admin/subscribers/export` endpoint handlers,
+//! and the listing shared with the API.
+//!
+use crate::authentication::{require_permission, Permission, UserId};
+use crate::domain::SubscriptionStatus;
+use crate::problem_details::ProblemDetails;
+use crate::subscriber_export::{export_subscribers, ExportFormat};
+use crate::utils::error_chain_fmt;
+use actix_web::http::header::CONTENT_DISPOSITION;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Number of subscribers per page, unless the client asks for another one.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Maximum number of subscribers per page.
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Query string shape for subscriber listings.
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    status: Option<SubscriptionStatus>,
+    /// Only subscribers who signed up at this time or later.
+    subscribed_after: Option<DateTime<Utc>>,
+    /// Only subscribers who signed up before this time.
+    subscribed_before: Option<DateTime<Utc>>,
+    /// Case-insensitive substring of the email or name.
+    search: Option<String>,
+    #[serde(default)]
+    order: SortOrder,
+    /// `next_cursor` of the previous page. The first page is returned if it's missing.
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Order of subscribers by signup time.
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    /// Newest subscribers first.
+    #[default]
+    Desc,
+}
+
+/// Page of subscribers, returned as JSON.
+#[derive(serde::Serialize)]
+pub struct SubscribersPage {
+    subscribers: Vec<Subscriber>,
+    /// Pass it as `cursor` to get the next page. `null` on the last page.
+    next_cursor: Option<String>,
+}
+
+/// Subscriber as listed to admins.
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+/// Failure of subscriber listings.
+#[derive(thiserror::Error)]
+pub enum ListSubscribersError {
+    #[error("The cursor is invalid. Pass `next_cursor` of the previous page as it is.")]
+    InvalidCursor,
+    #[error("The limit must be between 1 and {MAX_PAGE_SIZE}.")]
+    InvalidLimit,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListSubscribersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListSubscribersError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ListSubscribersError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = ProblemDetails::new(self.status_code());
+        match self {
+            ListSubscribersError::InvalidCursor => problem
+                .detail(self.to_string())
+                .invalid_param("cursor", self.to_string())
+                .response(),
+            ListSubscribersError::InvalidLimit => problem
+                .detail(self.to_string())
+                .invalid_param("limit", self.to_string())
+                .response(),
+            ListSubscribersError::UnexpectedError(_) => problem.response(),
+        }
+    }
+}
+
+/// Return a page of subscribers matching the filters of the query string, as JSON.
+/// Requires a role permitted to view subscribers.
+#[tracing::instrument(skip(query, pool), fields(user_id = %*user_id))]
+pub async fn admin_subscribers(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ViewSubscribers, &pool).await?;
+    let page = list_subscribers(&pool, &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Query string shape for `admin_subscribers_export` endpoint.
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Download all subscribers as a CSV or NDJSON file, depending on `format`.
+/// Requires a role permitted to view subscribers.
+// The file is streamed with chunked transfer encoding as it's produced,
+// whatever the size of the list.
+#[tracing::instrument(skip(query, pool), fields(user_id = %*user_id, format = ?query.format))]
+pub async fn admin_subscribers_export(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ViewSubscribers, &pool).await?;
+    let format = query.format;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!(
+                r#"attachment; filename="subscribers.{}""#,
+                format.extension()
+            ),
+        ))
+        .streaming(export_subscribers(pool.get_ref().clone(), format)))
+}
+
+/// Return the page of subscribers matching `query`.
+// Pages are delimited by `(subscribed_at, id)` of their last subscriber rather than by offset,
+// so that signups happening while the client pages through the list don't shift the pages,
+// and deep pages are as cheap as the first one.
+#[tracing::instrument(name = "List subscribers", skip_all)]
+pub async fn list_subscribers(
+    pool: &PgPool,
+    query: &SubscribersQuery,
+) -> Result<SubscribersPage, ListSubscribersError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ListSubscribersError::InvalidLimit);
+    }
+    let (after_time, after_id) = match &query.cursor {
+        Some(cursor) => {
+            let (time, id) = decode_cursor(cursor).ok_or(ListSubscribersError::InvalidCursor)?;
+            (Some(time), Some(id))
+        }
+        None => (None, None),
+    };
+    let status = query.status.map(|s| s.as_str());
+    let search = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(like_pattern);
+    // Fetch one more row than asked, to know whether there is a next page.
+    let fetch_limit = limit + 1;
+
+    let mut subscribers = match query.order {
+        SortOrder::Desc => {
+            sqlx::query_as!(
+                Subscriber,
+                r#"
+            SELECT id, email, name, status, subscribed_at
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+                AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+                AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+                AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)
+                AND ($5::timestamptz IS NULL OR (subscribed_at, id) < ($5, $6::uuid))
+            ORDER BY subscribed_at DESC, id DESC
+            LIMIT $7
+            "#,
+                status,
+                query.subscribed_after,
+                query.subscribed_before,
+                search,
+                after_time,
+                after_id,
+                fetch_limit
+            )
+            .fetch_all(pool)
+            .await
+        }
+        SortOrder::Asc => {
+            sqlx::query_as!(
+                Subscriber,
+                r#"
+            SELECT id, email, name, status, subscribed_at
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+                AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+                AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+                AND ($4::text IS NULL OR email ILIKE $4 OR name ILIKE $4)
+                AND ($5::timestamptz IS NULL OR (subscribed_at, id) > ($5, $6::uuid))
+            ORDER BY subscribed_at ASC, id ASC
+            LIMIT $7
+            "#,
+                status,
+                query.subscribed_after,
+                query.subscribed_before,
+                search,
+                after_time,
+                after_id,
+                fetch_limit
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+    .context("Failed to perform a query to list subscribers.")?;
+
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers
+            .last()
+            .map(|last| encode_cursor(last.subscribed_at, last.id))
+    } else {
+        None
+    };
+    Ok(SubscribersPage {
+        subscribers,
+        next_cursor,
+    })
+}
+
+/// Return opaque cursor pointing after the subscriber with `subscribed_at` and `id`.
+fn encode_cursor(subscribed_at: DateTime<Utc>, id: Uuid) -> String {
+    // Postgres keeps microseconds: so must the cursor, or it would skip or repeat rows.
+    let time = subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("{}/{}", time, id))
+}
+
+/// Return `subscribed_at` and `id` encoded by `encode_cursor`, or `None` if `cursor` is invalid.
+fn decode_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid)> {
+    let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (time, id) = decoded.split_once('/')?;
+    let time = DateTime::parse_from_rfc3339(time).ok()?.with_timezone(&Utc);
+    let id = Uuid::parse_str(id).ok()?;
+    Some((time, id))
+}
+
+/// Return `ILIKE` pattern matching strings containing `search`, with wildcards in it escaped.
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_cursor, encode_cursor, like_pattern};
+    use chrono::{TimeZone, Utc};
+    use claim::assert_none;
+    use uuid::Uuid;
+
+    #[test]
+    fn cursor_round_trips_with_microseconds() {
+        let time = Utc.timestamp_opt(1_687_000_000, 123_456_000).unwrap();
+        let id = Uuid::new_v4();
+
+        assert_eq!(decode_cursor(&encode_cursor(time, id)), Some((time, id)));
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        assert_none!(decode_cursor("not base64!"));
+        assert_none!(decode_cursor("bm90IGEgY3Vyc29y"));
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(like_pattern("john"), "%john%");
+        assert_eq!(like_pattern("100%_\\"), "%100\\%\\_\\\\%");
+    }
+}
@@ -0,0 +1,8 @@
+mod get;
+mod post;
+
+pub use get::{
+    admin_subscribers, admin_subscribers_export, list_subscribers, ListSubscribersError, SortOrder,
+    Subscriber, SubscribersPage, SubscribersQuery,
+};
+pub use post::import_subscribers;
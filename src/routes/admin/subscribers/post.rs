@@ -0,0 +1,40 @@
+//!
+//! Contains `POST /admin/subscribers/import` endpoint handler.
+//!
+use crate::authentication::{require_permission, Permission, UserId};
+use crate::subscriber_import::SubscriberImport;
+use actix_web::{web, HttpResponse};
+use futures_util::StreamExt;
+use sqlx::PgPool;
+
+/// Query string shape for `import_subscribers` endpoint.
+#[derive(serde::Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Import subscribers from the CSV file in the request body, and return the report as JSON.
+/// Requires a role permitted to manage subscribers.
+// The body is processed as it arrives, so it's sent as is (`Content-Type: text/csv`)
+// rather than as a multipart form: e.g. `curl --data-binary @subscribers.csv`.
+#[tracing::instrument(
+    skip(query, body, pool),
+    fields(user_id = %*user_id, dry_run = query.dry_run)
+)]
+pub async fn import_subscribers(
+    query: web::Query<ImportQuery>,
+    mut body: web::Payload,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ManageSubscribers, &pool).await?;
+
+    let mut import = SubscriberImport::new(&pool, query.dry_run);
+    while let Some(chunk) = body.next().await {
+        import.feed(&chunk?).await?;
+    }
+    let report = import.finish().await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,92 @@
+//!
+//! Contains `GET /admin/users` endpoint handler.
+//!
+use crate::authentication::{get_users, require_permission, Permission, Role, StoredUser, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// Return the list of admin users, with forms to manage them. Owners only.
+pub async fn users(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ManageUsers, &pool).await?;
+    users_page(StatusCode::OK, &pool, None).await
+}
+
+/// Render the users page with `status`, and with `message_html` above the list, if any.
+/// `message_html` is not escaped.
+pub(super) async fn users_page(
+    status: StatusCode,
+    pool: &PgPool,
+    message_html: Option<&str>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let users = get_users(pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for user in &users {
+        write_user_row(&mut rows_html, user).map_err(e500)?;
+    }
+    Ok(HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin users</title>
+</head>
+<body>
+    {message_html}
+    <table>
+        <tr>
+            <th>Username</th>
+            <th>Role</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/users" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter username" name="username">
+        </label>
+        <br>
+        <label>Role
+            {role_select}
+        </label>
+        <br>
+        <button type="submit">Invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            message_html = message_html.unwrap_or_default(),
+            role_select = role_select(Role::Editor),
+        )))
+}
+
+/// Append the table row of `user` to `html`, with forms changing their role and removing them.
+fn write_user_row(html: &mut String, user: &StoredUser) -> std::fmt::Result {
+    write!(
+        html,
+        r#"<tr><td>{username}</td><td><form action="/admin/users/{user_id}/role" method="post">{role_select}<button type="submit">Change role</button></form></td><td><form action="/admin/users/{user_id}/delete" method="post"><button type="submit">Remove</button></form></td></tr>"#,
+        username = htmlescape::encode_minimal(&user.username),
+        user_id = user.user_id,
+        role_select = role_select(user.role),
+    )
+}
+
+/// Return `<select>` element of `role` form field, with `selected` role preselected.
+fn role_select(selected: Role) -> String {
+    let options: String = Role::ALL
+        .iter()
+        .map(|role| {
+            let selected = if *role == selected { " selected" } else { "" };
+            format!(r#"<option value="{role}"{selected}>{role}</option>"#)
+        })
+        .collect();
+    format!(r#"<select name="role">{options}</select>"#)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::users;
+pub use post::{change_user_role, invite_user, remove_user};
@@ -0,0 +1,104 @@
+//!
+//! Contains `POST /admin/users`, `POST /admin/users/{user_id}/role`
+//! and `POST /admin/users/{user_id}/delete` endpoint handlers.
+//!
+use super::get::users_page;
+use crate::authentication::{
+    self, require_permission, Permission, Role, UserId, UserManagementError,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Form data shape for `invite_user` endpoint.
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    username: String,
+    role: String,
+}
+
+/// Form data shape for `change_user_role` endpoint.
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+/// Create an admin user with the role from the form, and show their temporary password.
+/// Owners only.
+#[tracing::instrument(skip(form, pool), fields(user_id = %*user_id, username = %form.username))]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ManageUsers, &pool).await?;
+    let role = match Role::parse(&form.role) {
+        Ok(role) => role,
+        Err(message) => return error_page(&pool, &message).await,
+    };
+    match authentication::invite_user(&pool, &form.username, role).await {
+        Ok(password) => {
+            let message_html = format!(
+                "<p><i>{} can now log in with the temporary password <code>{}</code>. \
+                It won't be shown again.</i></p>",
+                htmlescape::encode_minimal(form.username.trim()),
+                password.expose_secret()
+            );
+            users_page(StatusCode::OK, &pool, Some(&message_html)).await
+        }
+        Err(e) => management_error_page(&pool, e).await,
+    }
+}
+
+/// Give the user the role from the form. Owners only.
+#[tracing::instrument(skip(form, pool), fields(user_id = %*user_id))]
+pub async fn change_user_role(
+    target_user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ManageUsers, &pool).await?;
+    let role = match Role::parse(&form.role) {
+        Ok(role) => role,
+        Err(message) => return error_page(&pool, &message).await,
+    };
+    match authentication::change_role(&pool, target_user_id.into_inner(), role).await {
+        Ok(()) => Ok(see_other("/admin/users")),
+        Err(e) => management_error_page(&pool, e).await,
+    }
+}
+
+/// Remove the user. Owners only.
+#[tracing::instrument(skip(pool), fields(user_id = %*user_id))]
+pub async fn remove_user(
+    target_user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_permission(**user_id, Permission::ManageUsers, &pool).await?;
+    match authentication::remove_user(&pool, target_user_id.into_inner()).await {
+        Ok(()) => Ok(see_other("/admin/users")),
+        Err(e) => management_error_page(&pool, e).await,
+    }
+}
+
+/// Show the users page with the message of `e`, or fail with `500` if `e` is unexpected.
+async fn management_error_page(
+    pool: &PgPool,
+    e: UserManagementError,
+) -> Result<HttpResponse, actix_web::Error> {
+    match e {
+        UserManagementError::UnexpectedError(e) => Err(e500(e)),
+        e => error_page(pool, &e.to_string()).await,
+    }
+}
+
+/// Show the users page with `400 BAD REQUEST` and `message`.
+async fn error_page(pool: &PgPool, message: &str) -> Result<HttpResponse, actix_web::Error> {
+    let message_html = format!("<p><i>{}</i></p>", htmlescape::encode_minimal(message));
+    users_page(StatusCode::BAD_REQUEST, pool, Some(&message_html)).await
+}
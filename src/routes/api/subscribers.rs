@@ -0,0 +1,92 @@
+//!
+//! Contains `/api/subscribers` endpoint handlers, used by machine clients with API keys.
+//!
+use crate::authentication::{ApiKey, ReadSubscribers, WriteSubscribers};
+use crate::domain::{NewSubscriber, NewSubscriberError, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::lists::{get_lists, select_lists_or_all};
+use crate::routes::{
+    list_subscribers, register_subscriber, ListSubscribersError, SubscribeError, SubscribersQuery,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::time_zones::parse_time_zone;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// JSON body shape for `add_subscriber` endpoint.
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    email: String,
+    name: String,
+    /// Slugs of the chosen lists. None means all lists.
+    #[serde(default)]
+    lists: Vec<String>,
+    /// IANA time zone of the subscriber, e.g. `Europe/Berlin`. None means unknown.
+    time_zone: Option<String>,
+}
+
+impl TryFrom<BodyData> for NewSubscriber {
+    type Error = NewSubscriberError;
+
+    fn try_from(value: BodyData) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(value.name)?;
+        let email = SubscriberEmail::parse(value.email)?;
+        Ok(Self { email, name })
+    }
+}
+
+/// Add a new subscriber on behalf of a machine client, and send them a confirmation email.
+// Requires an API key with `subscribers:write` scope. The subscriber still has to confirm,
+// exactly as if they had signed up with the form: see `subscribe`.
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(body, pool, email_client, base_url, api_key),
+    fields(
+        key_id = %api_key.key_id,
+        subscriber_name = %body.name,
+        subscriber_email = %body.email
+    )
+)]
+pub async fn add_subscriber(
+    api_key: ApiKey<WriteSubscribers>,
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let mut body = body.into_inner();
+    let list_slugs = std::mem::take(&mut body.lists);
+    let time_zone = body.time_zone.take().unwrap_or_default();
+    let new_subscriber: NewSubscriber = body.try_into()?;
+    let lists = get_lists(&pool).await.context("Failed to fetch lists.")?;
+    let list_ids = select_lists_or_all(&lists, &list_slugs)?;
+    let time_zone = parse_time_zone(&pool, &time_zone).await?;
+    register_subscriber(
+        new_subscriber,
+        &list_ids,
+        time_zone.as_deref(),
+        &pool,
+        &email_client,
+        &base_url.0,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Return a page of subscribers matching the filters of the query string, as JSON.
+// Requires an API key with `subscribers:read` scope. Same listing as `admin_subscribers`.
+#[tracing::instrument(
+    name = "Listing subscribers through the API",
+    skip(query, pool, api_key),
+    fields(key_id = %api_key.key_id)
+)]
+pub async fn get_subscribers(
+    api_key: ApiKey<ReadSubscribers>,
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListSubscribersError> {
+    let page = list_subscribers(&pool, &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
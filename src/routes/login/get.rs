@@ -0,0 +1,42 @@
+//!
+//! Contains `GET /login` endpoint handler.
+//!
+use crate::flash::FlashMessages;
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+
+/// Return the login form, with the error of the previous attempt above it, if any.
+pub async fn login_form(flash: FlashMessages) -> HttpResponse {
+    let error_html = flash.incoming_html();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {error_html}
+    <form action="/login" method="post">
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+            >
+        </label>
+        <label>Password
+            <input
+                type="password"
+                placeholder="Enter Password"
+                name="password"
+            >
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
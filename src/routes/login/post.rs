@@ -0,0 +1,74 @@
+//!
+//! Contains `POST /login` endpoint handler.
+//!
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::flash::FlashMessages;
+use crate::session::TypedSession;
+use crate::utils::{error_chain_fmt, see_other};
+use actix_web::error::InternalError;
+use actix_web::{web, HttpResponse};
+use secrecy::Secret;
+use sqlx::PgPool;
+
+/// Form data shape for `login` endpoint.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+    password: Secret<String>,
+}
+
+/// Failure of `login` endpoint.
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Something went wrong.")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Log the user in, and redirect them to the admin dashboard.
+/// On failure, redirect them back to the login form, which shows the error.
+// The session gets a new key on login, so that a session key planted by an attacker
+// before login (session fixation) is useless afterwards.
+#[tracing::instrument(
+    skip(form, pool, session, flash),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash: FlashMessages,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, &pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+        })
+        .map_err(|e| login_redirect(e, &flash))?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    session.renew();
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into()), &flash))?;
+    Ok(see_other("/admin/dashboard"))
+}
+
+/// Redirect back to the login form, showing `e` there.
+fn login_redirect(e: LoginError, flash: &FlashMessages) -> InternalError<LoginError> {
+    flash.error(e.to_string());
+    InternalError::from_response(e, see_other("/login"))
+}
@@ -1,5 +1,21 @@
+mod admin;
+mod api;
 mod health_check;
+mod login;
+mod newsletters;
+mod preferences;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_unsubscribe;
 
+pub use admin::*;
+pub use api::*;
 pub use health_check::*;
+pub use login::*;
+pub use newsletters::*;
+pub use preferences::*;
 pub use subscriptions::*;
+pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_unsubscribe::*;
@@ -0,0 +1,599 @@
+//!
+//! Contains `/newsletters` endpoint handlers.
+//!
+use crate::authentication::{
+    require_permission, validate_credentials, ApiKey, AuthError, Credentials, Permission,
+    PublishNewsletters,
+};
+use crate::domain::SubscriberEmail;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_scheduler::{
+    cancel_issue, reschedule_issue, resolve_schedule, Schedule, DEFAULT_TIME_ZONE,
+};
+use crate::lists::{get_lists, select_lists_or_all};
+use crate::problem_details::ProblemDetails;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use base64::Engine;
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// JSON body shape for `publish_newsletter` endpoint.
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// Slugs of the lists the issue is published to. None means all lists.
+    #[serde(default)]
+    lists: Vec<String>,
+    /// Local date and time to publish the issue at, e.g. `2023-07-03T08:00`.
+    /// None means right away.
+    scheduled_for: Option<String>,
+    /// IANA time zone of `scheduled_for`.
+    #[serde(default = "default_time_zone")]
+    time_zone: String,
+    /// Deliver the scheduled issue at `scheduled_for` in the time zone of each subscriber.
+    #[serde(default)]
+    in_subscriber_time_zones: bool,
+}
+
+/// JSON body shape for `reschedule_newsletter` endpoint.
+#[derive(serde::Deserialize)]
+pub struct ScheduleBodyData {
+    /// Local date and time to publish the issue at, e.g. `2023-07-03T08:00`.
+    scheduled_for: String,
+    /// IANA time zone of `scheduled_for`.
+    #[serde(default = "default_time_zone")]
+    time_zone: String,
+    /// Deliver the issue at `scheduled_for` in the time zone of each subscriber.
+    #[serde(default)]
+    in_subscriber_time_zones: bool,
+}
+
+fn default_time_zone() -> String {
+    DEFAULT_TIME_ZONE.into()
+}
+
+/// Newsletter issue content, in both HTML and plain text.
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+/// Report returned to the caller after the issue has been queued for delivery.
+#[derive(serde::Serialize)]
+pub struct PublishReport {
+    pub newsletter_issue_id: Uuid,
+    pub queued: usize,
+    pub skipped: Vec<SkippedSubscriber>,
+}
+
+/// Report returned to the caller after the issue has been scheduled.
+#[derive(serde::Serialize)]
+pub struct ScheduleReport {
+    pub newsletter_issue_id: Uuid,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+}
+
+/// Confirmed subscriber the issue won't be delivered to, because their stored email is invalid.
+#[derive(serde::Serialize)]
+pub struct SkippedSubscriber {
+    pub email: String,
+    pub reason: String,
+}
+
+/// Store newsletter issue and queue its delivery to confirmed subscribers of its lists,
+/// or schedule it for later.
+// Emails are sent by `IssueDeliveryWorker` in background, so that the request doesn't time out
+// for large lists.
+//
+// Subscribers whose stored email doesn't pass `SubscriberEmail::parse` anymore (e.g. validation
+// rules were tightened after they signed up) are skipped and listed in the response body.
+//
+// The issue goes to every list unless the body names some in `lists`: clients written before
+// lists existed keep reaching everyone. Return `400 BAD REQUEST` with problem details if a list
+// is unknown.
+//
+// With `scheduled_for`, the issue is only stored: `IssueDeliveryWorker` queues its delivery once
+// it is due, and the response is a `ScheduleReport`. Return `400 BAD REQUEST` with problem
+// details if the time is invalid or past, or the time zone is unknown. With
+// `in_subscriber_time_zones` too, each subscriber gets it at that local time in their own time
+// zone, or in `time_zone` if theirs is unknown: see `issue_scheduler`.
+//
+// Clients must send a unique `Idempotency-Key` header with every new issue: a retried request
+// with the same key gets the response saved for the first one, and nothing is queued twice.
+// Return `400 BAD REQUEST` if the header is missing or invalid.
+//
+// Return `401 UNAUTHORIZED` if the request doesn't carry valid user credentials or API key,
+// and `403 FORBIDDEN` if the API key doesn't grant `newsletters:publish` scope or the user's
+// role doesn't permit publishing.
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let user_id = match authenticate(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let idempotency_key = match idempotency_key(request.headers()) {
+        Ok(idempotency_key) => idempotency_key,
+        Err(e) => {
+            tracing::warn!("Invalid idempotency key: {}", e);
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    let lists = match get_lists(&pool).await {
+        Ok(lists) => lists,
+        Err(e) => {
+            tracing::error!("Failed to fetch lists: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let list_ids = match select_lists_or_all(&lists, &body.lists) {
+        Ok(list_ids) => list_ids,
+        Err(e) => {
+            return ProblemDetails::new(StatusCode::BAD_REQUEST)
+                .detail(e.to_string())
+                .invalid_param("lists", e.to_string())
+                .response()
+        }
+    };
+    let schedule = match &body.scheduled_for {
+        Some(local_time) => match resolve_schedule(
+            &pool,
+            local_time,
+            &body.time_zone,
+            body.in_subscriber_time_zones,
+        )
+        .await
+        {
+            Ok(schedule) => Some(schedule),
+            Err(e) => return e.error_response(),
+        },
+        None => None,
+    };
+    // The issue, its deliveries and the saved response must be stored together, or not at all.
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id).await {
+        Ok(NextAction::StartProcessing(transaction)) => transaction,
+        Ok(NextAction::ReturnSavedResponse(saved_response)) => return saved_response,
+        Err(e) => {
+            tracing::error!("Failed to process idempotency key: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let response = match schedule {
+        Some(schedule) => schedule_newsletter_issue(
+            &mut transaction,
+            &body.title,
+            &body.content.text,
+            &body.content.html,
+            &list_ids,
+            &schedule,
+        )
+        .await
+        .map(|newsletter_issue_id| {
+            HttpResponse::Ok().json(ScheduleReport {
+                newsletter_issue_id,
+                schedule,
+            })
+        }),
+        None => queue_newsletter_issue(
+            &mut transaction,
+            &body.title,
+            &body.content.text,
+            &body.content.html,
+            &list_ids,
+        )
+        .await
+        .map(|report| HttpResponse::Ok().json(report)),
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match save_response(transaction, &idempotency_key, user_id, response).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::error!("Failed to save response for idempotency key: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Move the scheduled issue to the time and time zone of the body.
+// Same authentication as `publish_newsletter`. Return `404 NOT FOUND` if there is no such issue,
+// and `409 CONFLICT` if it isn't scheduled anymore: its delivery has started, or it was
+// cancelled.
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(body, pool, request),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn reschedule_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<ScheduleBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let user_id = match authenticate(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let schedule = match resolve_schedule(
+        &pool,
+        &body.scheduled_for,
+        &body.time_zone,
+        body.in_subscriber_time_zones,
+    )
+    .await
+    {
+        Ok(schedule) => schedule,
+        Err(e) => return e.error_response(),
+    };
+    match reschedule_issue(&pool, newsletter_issue_id, &schedule).await {
+        Ok(()) => HttpResponse::Ok().json(ScheduleReport {
+            newsletter_issue_id,
+            schedule,
+        }),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Cancel the scheduled issue. It is kept, but never delivered.
+// Same authentication and failures as `reschedule_newsletter`.
+#[tracing::instrument(
+    name = "Cancel a newsletter issue",
+    skip(pool, request),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn cancel_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let user_id = match authenticate(&request, &pool).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    match cancel_issue(&pool, newsletter_issue_id.into_inner()).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Return the id of the user the request acts for.
+///
+/// Machine clients authenticate with an API key granting `newsletters:publish` scope,
+/// people with their username and password, using Basic authentication. Either way, the role
+/// of the user must permit publishing.
+async fn authenticate(request: &HttpRequest, pool: &PgPool) -> Result<Uuid, HttpResponse> {
+    let is_bearer = request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Bearer "));
+    if is_bearer {
+        return ApiKey::<PublishNewsletters>::authenticate(request.headers(), pool)
+            .await
+            .map(|api_key| api_key.user_id)
+            .map_err(|e| {
+                tracing::warn!(error.cause_chain = ?e, "Failed to authenticate API key.");
+                e.error_response()
+            });
+    }
+
+    let credentials = basic_authentication(request.headers()).map_err(|e| {
+        tracing::warn!("Failed to authenticate: {}", e);
+        unauthorized()
+    })?;
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(e) => {
+                tracing::warn!(error.cause_chain = ?e, "Failed to authenticate.");
+                unauthorized()
+            }
+            AuthError::UnexpectedError(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to validate credentials.");
+                HttpResponse::InternalServerError().finish()
+            }
+        })?;
+    require_permission(user_id, Permission::PublishNewsletters, pool)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Failed to authorize: {}", e);
+            e.error_response()
+        })?;
+
+    Ok(user_id)
+}
+
+/// Store newsletter issue published to `list_ids`, and queue its delivery to confirmed
+/// subscribers of any of them with valid emails. Return the report of what was queued
+/// and skipped.
+pub async fn queue_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    list_ids: &[Uuid],
+) -> Result<PublishReport, sqlx::Error> {
+    let newsletter_issue_id = insert_newsletter_issue(
+        transaction,
+        title,
+        text_content,
+        html_content,
+        list_ids,
+        None,
+    )
+    .await?;
+    enqueue_newsletter_issue(transaction, newsletter_issue_id, list_ids).await
+}
+
+/// Store newsletter issue to be published to `list_ids` on `schedule`.
+/// Return the id of the new issue.
+pub async fn schedule_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    list_ids: &[Uuid],
+    schedule: &Schedule,
+) -> Result<Uuid, sqlx::Error> {
+    insert_newsletter_issue(
+        transaction,
+        title,
+        text_content,
+        html_content,
+        list_ids,
+        Some(schedule),
+    )
+    .await
+}
+
+/// Queue delivery of the stored issue to confirmed subscribers of any of `list_ids` with
+/// valid emails. Return the report of what was queued and skipped.
+pub async fn enqueue_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<PublishReport, sqlx::Error> {
+    let subscribers = get_confirmed_subscribers(transaction, list_ids).await?;
+
+    let mut recipients = vec![];
+    let mut skipped = vec![];
+    for email in subscribers {
+        match SubscriberEmail::parse(email.clone()) {
+            Ok(email) => recipients.push(email.as_ref().to_owned()),
+            Err(reason) => {
+                tracing::warn!(
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid: {}",
+                    reason
+                );
+                skipped.push(SkippedSubscriber {
+                    email,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+    enqueue_delivery_tasks(transaction, newsletter_issue_id, &recipients).await?;
+
+    Ok(PublishReport {
+        newsletter_issue_id,
+        queued: recipients.len(),
+        skipped,
+    })
+}
+
+/// Extract idempotency key from the `Idempotency-Key` header.
+fn idempotency_key(headers: &HeaderMap) -> Result<IdempotencyKey, String> {
+    let header_value = headers
+        .get("Idempotency-Key")
+        .ok_or("The 'Idempotency-Key' header was missing.")?
+        .to_str()
+        .map_err(|_| "The 'Idempotency-Key' header was not a valid UTF8 string.")?;
+    header_value.to_string().try_into()
+}
+
+/// Return `401 UNAUTHORIZED` response, asking the client to use Basic authentication.
+fn unauthorized() -> HttpResponse {
+    let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+    response.headers_mut().insert(
+        actix_web::http::header::WWW_AUTHENTICATE,
+        HeaderValue::from_static(r#"Basic realm="publish""#),
+    );
+    response
+}
+
+/// Extract credentials from the `Authorization` header, using Basic authentication scheme.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, String> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .ok_or("The 'Authorization' header was missing.")?
+        .to_str()
+        .map_err(|_| "The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .map_err(|_| "Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| "The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimiter
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or("A password must be provided in 'Basic' auth.")?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+/// Return emails of subscribers with `confirmed` status, subscribed to any of `list_ids`.
+#[tracing::instrument(name = "Get confirmed subscribers", skip(transaction))]
+async fn get_confirmed_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_ids: &[Uuid],
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email AS "email!"
+        FROM subscriptions
+        WHERE
+            status = 'confirmed' AND
+            EXISTS (
+                SELECT 1 FROM subscription_lists
+                WHERE subscriber_id = subscriptions.id AND list_id = ANY($1)
+            )
+        "#,
+        list_ids
+    )
+    .fetch_all(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(rows.into_iter().map(|r| r.email).collect())
+}
+
+/// Store newsletter issue content, and the lists it is published to: as published right away,
+/// or as scheduled if there is a `schedule`. Return the id of the new issue.
+#[tracing::instrument(name = "Save newsletter issue", skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    list_ids: &[Uuid],
+    schedule: Option<&Schedule>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let (status, published_at) = match schedule {
+        Some(_) => ("scheduled", None),
+        None => ("published", Some(Utc::now())),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            status,
+            published_at,
+            scheduled_for,
+            scheduled_time_zone,
+            in_subscriber_time_zones
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content,
+        status,
+        published_at,
+        schedule.map(|s| s.scheduled_for),
+        schedule.map(|s| s.time_zone.as_str()),
+        schedule.map_or(false, |s| s.in_subscriber_time_zones)
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+        "#,
+        newsletter_issue_id,
+        list_ids
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(newsletter_issue_id)
+}
+
+/// Queue delivery of the issue to each of `recipients`: on next Monday at midnight for those
+/// getting issues weekly, and right away for the others, unless the issue is scheduled in
+/// subscriber time zones. Then, it is due at the scheduled local time in the time zone of each
+/// subscriber, or right away if that time has already passed there.
+// Local times are resolved in the time zone of the subscriber, falling back to UTC for weekly
+// issues and to the time zone of the schedule for the others, after the arithmetic: the next
+// Monday is midnight in Berlin whether a daylight saving time transition is on the way or not.
+#[tracing::instrument(name = "Enqueue delivery tasks", skip(transaction, recipients))]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    recipients: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, execute_after)
+        SELECT
+            $1,
+            s.email,
+            CASE
+                WHEN s.delivery_frequency = 'weekly' THEN
+                    (
+                        date_trunc('week', now() AT TIME ZONE COALESCE(s.time_zone, 'UTC'))
+                        + interval '1 week'
+                    ) AT TIME ZONE COALESCE(s.time_zone, 'UTC')
+                WHEN i.in_subscriber_time_zones THEN
+                    GREATEST(
+                        now(),
+                        (i.scheduled_for AT TIME ZONE i.scheduled_time_zone)
+                            AT TIME ZONE COALESCE(s.time_zone, i.scheduled_time_zone)
+                    )
+                ELSE now()
+            END
+        FROM subscriptions s
+        JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+        WHERE s.email = ANY($2)
+        "#,
+        newsletter_issue_id,
+        recipients
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//!
+//! Contains `/preferences` endpoint handlers, letting subscribers choose their lists,
+//! how often they get issues, and their time zone.
+//!
+use crate::domain::DeliveryFrequency;
+use crate::flash::FlashMessages;
+use crate::lists::{get_lists, get_subscriber_lists, select_lists, set_subscriber_lists};
+use crate::routes::set_subscriber_time_zone;
+use crate::time_zones::{parse_time_zone, TimeZoneError};
+use crate::utils::{e400, e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_lab::extract::UrlEncodedForm;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Query parameters shape for `preferences_form` endpoint.
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    unsubscribe_token: String,
+}
+
+/// Form data shape for `save_preferences` endpoint.
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    unsubscribe_token: String,
+    /// Slugs of the checked lists, one field per list.
+    #[serde(default)]
+    lists: Vec<String>,
+    frequency: DeliveryFrequency,
+    /// IANA time zone of the subscriber, e.g. `Europe/Berlin`. Empty means unknown.
+    #[serde(default)]
+    time_zone: String,
+}
+
+/// Return the preferences link for the subscriber owning `unsubscribe_token`.
+pub fn preferences_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!(
+        "{}/preferences?unsubscribe_token={}",
+        base_url, unsubscribe_token
+    )
+}
+
+/// Return the form with the lists, delivery frequency and time zone of the subscriber owning
+/// the token, with the outcome of the previous submission above it, if any.
+// A blank time zone is filled in with the one of the browser, which the subscriber only
+// has to save.
+// The unsubscribe token already lets its holder stop all deliveries: choosing which ones
+// to get doesn't need another secret.
+//
+// Return `401 UNAUTHORIZED` if the token is unknown.
+#[tracing::instrument(name = "Show preferences form", skip(parameters, pool, flash))]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = get_subscriber_by_unsubscribe_token(&pool, &parameters.unsubscribe_token)
+        .await
+        .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let subscriber_lists = get_subscriber_lists(&pool, subscriber.id)
+        .await
+        .map_err(e500)?;
+
+    let message_html = flash.incoming_html();
+    let list_checkboxes: String = lists
+        .iter()
+        .map(|list| {
+            let checked = if subscriber_lists.contains(&list.list_id) {
+                " checked"
+            } else {
+                ""
+            };
+            format!(
+                r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+                htmlescape::encode_attribute(&list.slug),
+                checked,
+                htmlescape::encode_minimal(&list.name),
+            )
+        })
+        .collect();
+    let frequency_radios: String = [
+        (
+            DeliveryFrequency::Immediately,
+            "As soon as they are published",
+        ),
+        (DeliveryFrequency::Weekly, "All together, on Monday"),
+    ]
+    .into_iter()
+    .map(|(frequency, label)| {
+        let checked = if subscriber.delivery_frequency == frequency.as_str() {
+            " checked"
+        } else {
+            ""
+        };
+        format!(
+            r#"<label><input type="radio" name="frequency" value="{}"{}> {}</label><br>"#,
+            frequency.as_str(),
+            checked,
+            label,
+        )
+    })
+    .collect();
+    let unsubscribe_token = htmlescape::encode_attribute(&parameters.unsubscribe_token);
+    let time_zone = htmlescape::encode_attribute(subscriber.time_zone.as_deref().unwrap_or(""));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preferences</title>
+</head>
+<body>
+    {message_html}
+    <form action="/preferences" method="post">
+        <fieldset>
+            <legend>Lists</legend>
+            {list_checkboxes}
+        </fieldset>
+        <fieldset>
+            <legend>Issues</legend>
+            {frequency_radios}
+        </fieldset>
+        <label>Time zone
+            <input type="text" id="time_zone" name="time_zone" value="{time_zone}" placeholder="Europe/Berlin">
+        </label>
+        <br>
+        <input hidden type="text" name="unsubscribe_token" value="{unsubscribe_token}">
+        <button type="submit">Save</button>
+    </form>
+    <script>
+        const timeZone = document.getElementById("time_zone");
+        if (!timeZone.value) {{
+            timeZone.value = Intl.DateTimeFormat().resolvedOptions().timeZone;
+        }}
+    </script>
+</body>
+</html>"#,
+        )))
+}
+
+/// Store the lists, delivery frequency and time zone from the form, and redirect back
+/// to the form, which shows the outcome.
+// Unchecking every list is allowed: the subscriber stays confirmed, but gets no issues.
+// A blank time zone is stored as unknown.
+//
+// Return `401 UNAUTHORIZED` if the token is unknown, and `400 BAD REQUEST` if a list
+// or the time zone is.
+#[tracing::instrument(name = "Save preferences", skip(form, pool, flash))]
+pub async fn save_preferences(
+    form: UrlEncodedForm<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+    flash: FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let PreferencesFormData {
+        unsubscribe_token,
+        lists: list_slugs,
+        frequency,
+        time_zone,
+    } = form.into_inner();
+    let subscriber = get_subscriber_by_unsubscribe_token(&pool, &unsubscribe_token)
+        .await
+        .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_ids = select_lists(&lists, &list_slugs).map_err(e400)?;
+    let time_zone = parse_time_zone(&pool, &time_zone)
+        .await
+        .map_err(|e| match e {
+            TimeZoneError::UnknownTimeZone(e) => e400(e),
+            TimeZoneError::UnexpectedError(e) => e500(e),
+        })?;
+
+    store_preferences(
+        &pool,
+        subscriber.id,
+        &list_ids,
+        frequency,
+        time_zone.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    flash.info("Your preferences have been saved.");
+    Ok(see_other(&preferences_link("", &unsubscribe_token)))
+}
+
+/// Subscriber owning an unsubscribe token.
+struct Subscriber {
+    id: Uuid,
+    delivery_frequency: String,
+    time_zone: Option<String>,
+}
+
+/// Return the subscriber owning `unsubscribe_token`, if any.
+#[tracing::instrument(
+    name = "Get subscriber from unsubscribe token",
+    skip(unsubscribe_token, pool)
+)]
+async fn get_subscriber_by_unsubscribe_token(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, delivery_frequency, time_zone
+        FROM subscriptions
+        WHERE unsubscribe_token = $1
+        "#,
+        unsubscribe_token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Subscribe the subscriber to exactly `list_ids`, and store their delivery frequency
+/// and time zone.
+#[tracing::instrument(name = "Store preferences", skip(pool))]
+async fn store_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+    frequency: DeliveryFrequency,
+    time_zone: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    set_subscriber_lists(&mut transaction, subscriber_id, list_ids)
+        .await
+        .context("Failed to store the lists of a subscriber.")?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET delivery_frequency = $1 WHERE id = $2"#,
+        frequency.as_str(),
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the delivery frequency of a subscriber.")?;
+    set_subscriber_time_zone(&mut transaction, subscriber_id, time_zone)
+        .await
+        .context("Failed to store the time zone of a subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store preferences.")?;
+
+    Ok(())
+}
@@ -1,10 +1,23 @@
 //!
 //! Contains `/subscriptions` endpoint handlers.
 //!
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use actix_web::{web, HttpResponse};
+use crate::domain::{
+    NewSubscriber, NewSubscriberError, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+use crate::email_client::EmailClient;
+use crate::lists::{get_lists, select_lists_or_all, set_subscriber_lists, UnknownListError};
+use crate::problem_details::ProblemDetails;
+use crate::routes::{preferences_link, unsubscribe_link};
+use crate::startup::ApplicationBaseUrl;
+use crate::time_zones::{parse_time_zone, TimeZoneError, UnknownTimeZoneError};
+use crate::utils::{error_chain_fmt, generate_token};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_lab::extract::UrlEncodedForm;
+use anyhow::Context;
 use chrono::Utc;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::Instrument;
 use uuid::Uuid;
 
 /// Form data shape for `subscribe` endpoint.
@@ -12,10 +25,16 @@ use uuid::Uuid;
 pub struct FormData {
     email: String,
     name: String,
+    /// Slugs of the chosen lists, one field per list. None means all lists.
+    #[serde(default)]
+    lists: Vec<String>,
+    /// IANA time zone of the subscriber, e.g. `Europe/Berlin`. Empty means unknown.
+    #[serde(default)]
+    time_zone: String,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = NewSubscriberError;
 
     /// Convert `FormData` to our domain-specific type `NewSubscriber`.
     // NB: If you provide a `TryFrom` implementation, your type automatically gets the corresponding
@@ -27,7 +46,72 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
-/// Add new subscriber to database using validated `FormData`.
+/// Failure of `subscribe` endpoint.
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    /// The form body couldn't be deserialized.
+    #[error("{0}")]
+    InvalidForm(String),
+    #[error(transparent)]
+    ValidationError(#[from] NewSubscriberError),
+    #[error(transparent)]
+    UnknownList(#[from] UnknownListError),
+    #[error(transparent)]
+    UnknownTimeZone(#[from] UnknownTimeZoneError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<TimeZoneError> for SubscribeError {
+    fn from(e: TimeZoneError) -> Self {
+        match e {
+            TimeZoneError::UnknownTimeZone(e) => e.into(),
+            TimeZoneError::UnexpectedError(e) => SubscribeError::UnexpectedError(e.into()),
+        }
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscribeError::InvalidForm(_)
+            | SubscribeError::ValidationError(_)
+            | SubscribeError::UnknownList(_)
+            | SubscribeError::UnknownTimeZone(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Tell the caller which field failed validation and why.
+    /// Details of unexpected errors are only logged, by `TracingLogger`.
+    fn error_response(&self) -> HttpResponse {
+        let problem = ProblemDetails::new(self.status_code());
+        match self {
+            SubscribeError::InvalidForm(e) => problem.detail(e).response(),
+            SubscribeError::ValidationError(e) => problem
+                .detail(e.to_string())
+                .invalid_param(e.field(), e.reason())
+                .response(),
+            SubscribeError::UnknownList(e) => problem
+                .detail(e.to_string())
+                .invalid_param("lists", e.to_string())
+                .response(),
+            SubscribeError::UnknownTimeZone(e) => problem
+                .detail(e.to_string())
+                .invalid_param("time_zone", e.to_string())
+                .response(),
+            SubscribeError::UnexpectedError(_) => problem.response(),
+        }
+    }
+}
+
+/// Add new subscriber to database using validated `FormData`, and send them a confirmation email.
 // Before calling `subscribe` actix-web invokes the `from_request` method for all subscribe’s
 // input arguments: in our case, `Form::from_request`;
 //
@@ -36,54 +120,332 @@ impl TryFrom<FormData> for NewSubscriber {
 // automatically generated for us by `#[derive(serde::Deserialize)]`;
 //
 // If `Form::from_request` fails, a `400 BAD REQUEST` is returned to the caller. If it succeeds,
-// `subscribe` is invoked and we return a `200 OK`.
+// `subscribe` is invoked and we return a `200 OK`. We use `UrlEncodedForm` rather than
+// `web::Form`, because the chosen lists are sent as repeated `lists` fields, which
+// `web::Form` rejects. Unlike `web::Form`, it ignores `web::FormConfig`: its errors are
+// turned into problem details here.
+//
+// Signups without `lists` get every list, as before lists existed. `time_zone` is optional:
+// scheduled issues delivered in subscriber time zones fall back to the time zone of the schedule.
+//
+// Signing up with an email which is already stored doesn't fail:
+// - a pending subscriber gets another confirmation email;
+// - an unsubscribed one is moved back to `pending_confirmation`, and gets a confirmation email;
+// - a confirmed one gets nothing, but the response is the same as for a new signup.
+// The confirmation email is sent in the background, so that neither the status nor the response
+// time reveal whether the email is on the list: a failure to send it is only logged.
 //
-// `pool` is retrieved from application state.
+// Validation failures are returned as `400 BAD REQUEST` with RFC 7807 problem details body,
+// naming the field which failed validation.
+//
+// `pool`, `email_client` and `base_url` are retrieved from application state.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool),
+    skip(form, pool, email_client, base_url),
     fields(
-        subscriber_name = %form.name,
-        subscriber_email =  %form.email
+        subscriber_name = tracing::field::Empty,
+        subscriber_email = tracing::field::Empty
     )
 )]
-pub async fn subscribe(form: web::Form<FormData>, pool: web::Data<PgPool>) -> HttpResponse {
-    let new_subscriber: NewSubscriber = match form.0.try_into() {
-        Ok(form) => form,
-        Err(_) => return HttpResponse::BadRequest().finish(),
-    };
+pub async fn subscribe(
+    form: Result<UrlEncodedForm<FormData>, actix_web::Error>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let mut form = form
+        .map_err(|e| SubscribeError::InvalidForm(e.to_string()))?
+        .into_inner();
+    let span = tracing::Span::current();
+    span.record("subscriber_name", tracing::field::display(&form.name));
+    span.record("subscriber_email", tracing::field::display(&form.email));
+    let list_slugs = std::mem::take(&mut form.lists);
+    let time_zone = std::mem::take(&mut form.time_zone);
+    let new_subscriber: NewSubscriber = form.try_into()?;
+    let lists = get_lists(&pool).await.context("Failed to fetch lists.")?;
+    let list_ids = select_lists_or_all(&lists, &list_slugs)?;
+    let time_zone = parse_time_zone(&pool, &time_zone).await?;
+    register_subscriber(
+        new_subscriber,
+        &list_ids,
+        time_zone.as_deref(),
+        &pool,
+        &email_client,
+        &base_url.0,
+    )
+    .await?;
 
-    match insert_subscriber(&pool, &new_subscriber).await {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Store `new_subscriber` with `pending_confirmation` status, subscribed to `list_ids`,
+/// in `time_zone` if known, and send them a confirmation email in the background, handling
+/// emails which are already stored as described for `subscribe`. The lists and time zone of
+/// a confirmed subscriber are left alone: they can change them from the preferences page.
+pub async fn register_subscriber(
+    new_subscriber: NewSubscriber,
+    list_ids: &[Uuid],
+    time_zone: Option<&str>,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    // The subscriber row and its token must be stored together, or not at all.
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let unsubscribe_token = generate_token();
+    let inserted = insert_subscriber(&mut transaction, &new_subscriber, &unsubscribe_token)
+        .await
+        .context("Failed to insert new subscriber in the database.")?;
+    let (subscriber_id, unsubscribe_token) = match inserted {
+        Some(subscriber_id) => (subscriber_id, unsubscribe_token),
+        None => {
+            let existing = get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+                .await
+                .context("Failed to fetch an existing subscriber from the database.")?;
+            let status = SubscriptionStatus::parse(&existing.status)
+                .context("The existing subscriber has an unknown status.")?;
+            match status {
+                SubscriptionStatus::Confirmed => {
+                    // Respond exactly like to a new signup, so that the response doesn't reveal
+                    // whether the email is on the list.
+                    tracing::info!("The subscriber is already confirmed.");
+                    return Ok(());
+                }
+                SubscriptionStatus::Unsubscribed => {
+                    tracing::info!("Reactivating an unsubscribed subscriber.");
+                    mark_subscriber_as_pending(&mut transaction, existing.id)
+                        .await
+                        .context("Failed to reactivate an unsubscribed subscriber.")?;
+                }
+                SubscriptionStatus::PendingConfirmation => {
+                    tracing::info!("Re-sending confirmation email to a pending subscriber.")
+                }
+            }
+            (existing.id, existing.unsubscribe_token)
+        }
+    };
+    set_subscriber_lists(&mut transaction, subscriber_id, list_ids)
+        .await
+        .context("Failed to store the lists of a new subscriber.")?;
+    if let Some(time_zone) = time_zone {
+        set_subscriber_time_zone(&mut transaction, subscriber_id, Some(time_zone))
+            .await
+            .context("Failed to store the time zone of a new subscriber.")?;
     }
+    let subscription_token = generate_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+
+    let email_client = email_client.clone();
+    let base_url = base_url.to_owned();
+    tokio::spawn(
+        async move {
+            let sent = send_confirmation_email(
+                &email_client,
+                new_subscriber,
+                &base_url,
+                &subscription_token,
+                &unsubscribe_token,
+            )
+            .await;
+            if let Err(e) = sent {
+                tracing::error!(error.cause_chain = ?e, "Failed to send a confirmation email.");
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    Ok(())
 }
 
-/// Insert subscriber row into database.
+/// Insert subscriber row into database, with `pending_confirmation` status.
+/// Return the id of the new subscriber, or `None` if the email is already stored.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, pool)
+    skip(new_subscriber, transaction, unsubscribe_token)
 )]
 pub async fn insert_subscriber(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    unsubscribe_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
         "#,
         Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        unsubscribe_token
     )
-    .execute(pool)
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(row.map(|r| r.id))
+}
+
+/// Subscriber already stored with the email of a new signup.
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+    unsubscribe_token: String,
+}
+
+/// Get the subscriber with `email`, locking the row until the transaction is over.
+#[tracing::instrument(name = "Get existing subscriber", skip(transaction, email))]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<ExistingSubscriber, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT id, status, unsubscribe_token
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        email.as_ref()
+    )
+    .fetch_one(transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+}
+
+/// Store the time zone of the subscriber, `None` if unknown.
+#[tracing::instrument(name = "Set subscriber time zone", skip(transaction))]
+pub async fn set_subscriber_time_zone(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    time_zone: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET time_zone = $1 WHERE id = $2"#,
+        time_zone,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
 
     Ok(())
 }
+
+/// Move the subscriber back to `pending_confirmation` status, until they confirm again.
+/// Their previous confirmation links stop working: only the one about to be sent does.
+#[tracing::instrument(name = "Mark subscriber as pending", skip(transaction))]
+async fn mark_subscriber_as_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'pending_confirmation', confirmed_at = NULL
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_tokens(transaction, subscriber_id).await?;
+
+    Ok(())
+}
+
+/// Store subscription token for the subscriber with `subscriber_id`.
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(subscription_token, transaction)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        subscription_token,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete the subscription tokens of the subscriber with `subscriber_id`, so that the
+/// confirmation links sent so far stop working.
+#[tracing::instrument(name = "Delete subscription tokens", skip(transaction))]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Send an email with the subscription confirmation link to the new subscriber.
+#[tracing::instrument(
+    name = "Send a confirmation email to a new subscriber",
+    skip(
+        email_client,
+        new_subscriber,
+        base_url,
+        subscription_token,
+        unsubscribe_token
+    )
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    new_subscriber: NewSubscriber,
+    base_url: &str,
+    subscription_token: &str,
+    unsubscribe_token: &str,
+) -> Result<(), anyhow::Error> {
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let preferences_link = preferences_link(base_url, unsubscribe_token);
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to confirm your subscription.<br />\
+        Choose your lists <a href=\"{}\">here</a>.",
+        confirmation_link, preferences_link
+    );
+    let text_body = format!(
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.\n\
+        Choose your lists at {}",
+        confirmation_link, preferences_link
+    );
+
+    email_client
+        .send_email(
+            new_subscriber.email,
+            "Welcome!",
+            &html_body,
+            &text_body,
+            &unsubscribe_link(base_url, unsubscribe_token),
+        )
+        .await
+}
@@ -0,0 +1,332 @@
+//!
+//! Contains `/subscriptions/data_requests`, `/subscriptions/data` and `/subscriptions/erase`
+//! endpoint handlers, letting subscribers access and erase their data.
+//!
+use crate::domain::{SubscriberEmail, SubscriberEmailError};
+use crate::email_client::EmailClient;
+use crate::problem_details::ProblemDetails;
+use crate::routes::unsubscribe_link;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_data::{erase_subscriber, get_subscriber_data, DataRequestKind};
+use crate::utils::{e500, error_chain_fmt, generate_token};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// How long the links emailed to subscribers are valid, in hours.
+const DATA_REQUEST_TOKEN_LIFETIME_HOURS: i64 = 24;
+
+/// Form data shape for `request_subscriber_data` endpoint.
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+    kind: DataRequestKind,
+}
+
+/// Query parameters shape for `subscriber_data` and `erasure_form` endpoints,
+/// and form data shape for `erase` endpoint.
+#[derive(serde::Deserialize)]
+pub struct DataRequestParameters {
+    data_request_token: String,
+}
+
+/// Failure of `request_subscriber_data` endpoint. Everything past the validation of the email
+/// happens in the background, and is only logged.
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("Invalid subscriber email.")]
+    InvalidEmail(#[from] SubscriberEmailError),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DataRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataRequestError::InvalidEmail(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Tell the caller why the email failed validation.
+    fn error_response(&self) -> HttpResponse {
+        let problem = ProblemDetails::new(self.status_code());
+        match self {
+            DataRequestError::InvalidEmail(e) => problem
+                .detail(self.to_string())
+                .invalid_param("email", e.to_string())
+                .response(),
+        }
+    }
+}
+
+/// Email a link to access or erase the data of the subscriber with the given email.
+// The response doesn't reveal whether the email is on the list: the lookup and the email are
+// handled in the background, so that neither the status nor the response time depend on it.
+// If the email is not on the list, nothing is sent. Only the owner of the mailbox gets
+// the link, which is valid for a day.
+#[tracing::instrument(
+    name = "Request subscriber data",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_email = %form.email, kind = ?form.kind)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, DataRequestError> {
+    let form = form.into_inner();
+    let email = SubscriberEmail::parse(form.email)?;
+    let pool = pool.get_ref().clone();
+    let base_url = base_url.0.clone();
+    tokio::spawn(
+        async move {
+            if let Err(e) =
+                send_data_request(&pool, &email_client, &base_url, email, form.kind).await
+            {
+                tracing::error!(error.cause_chain = ?e, "Failed to handle a data request.");
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Store a data request token of `kind` for the subscriber with `email`, if any, and email them
+/// the link.
+async fn send_data_request(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: SubscriberEmail,
+    kind: DataRequestKind,
+) -> Result<(), anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT id, unsubscribe_token FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber.")?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => {
+            tracing::info!("Ignoring a data request for an unknown email.");
+            return Ok(());
+        }
+    };
+
+    let data_request_token = generate_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO data_request_tokens (data_request_token, subscriber_id, kind, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        data_request_token,
+        subscriber.id,
+        kind.as_str(),
+        Utc::now() + Duration::hours(DATA_REQUEST_TOKEN_LIFETIME_HOURS)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a data request token.")?;
+    send_data_request_email(
+        email_client,
+        email,
+        kind,
+        base_url,
+        &data_request_token,
+        &subscriber.unsubscribe_token,
+    )
+    .await
+    .context("Failed to send a data request email.")
+}
+
+/// Return everything stored about the subscriber as JSON.
+// Return `401 UNAUTHORIZED` if the token is unknown, expired or not for an access request.
+#[tracing::instrument(name = "Get subscriber data by link", skip(parameters, pool))]
+pub async fn subscriber_data(
+    parameters: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = get_subscriber_id_from_data_request_token(
+        &pool,
+        &parameters.data_request_token,
+        DataRequestKind::Access,
+    )
+    .await
+    .map_err(e500)?;
+    let data = match subscriber_id {
+        Some(subscriber_id) => get_subscriber_data(&pool, subscriber_id)
+            .await
+            .map_err(e500)?,
+        None => None,
+    };
+    match data {
+        Some(data) => Ok(HttpResponse::Ok().json(data)),
+        None => Ok(HttpResponse::Unauthorized().finish()),
+    }
+}
+
+/// Ask the subscriber to confirm the erasure of their data.
+// The erasure itself is a `POST`, so that mail clients and scanners prefetching links
+// can't trigger it.
+//
+// Return `401 UNAUTHORIZED` if the token is unknown, expired or not for an erasure request.
+#[tracing::instrument(name = "Show erasure form", skip(parameters, pool))]
+pub async fn erasure_form(
+    parameters: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = get_subscriber_id_from_data_request_token(
+        &pool,
+        &parameters.data_request_token,
+        DataRequestKind::Erasure,
+    )
+    .await
+    .map_err(e500)?;
+    if subscriber_id.is_none() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Erase your data</title>
+</head>
+<body>
+    <p>Your subscription and everything we store about you will be deleted.
+    This cannot be undone.</p>
+    <form action="/subscriptions/erase" method="post">
+        <input hidden type="text" name="data_request_token" value="{}">
+        <button type="submit">Erase my data</button>
+    </form>
+</body>
+</html>"#,
+            htmlescape::encode_attribute(&parameters.data_request_token)
+        )))
+}
+
+/// Delete everything stored about the subscriber.
+// Return `401 UNAUTHORIZED` if the token is unknown, expired or not for an erasure request.
+#[tracing::instrument(name = "Erase subscriber by link", skip(form, pool))]
+pub async fn erase(
+    form: web::Form<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = get_subscriber_id_from_data_request_token(
+        &pool,
+        &form.data_request_token,
+        DataRequestKind::Erasure,
+    )
+    .await
+    .map_err(e500)?;
+    let erased = match subscriber_id {
+        Some(subscriber_id) => erase_subscriber(&pool, subscriber_id).await.map_err(e500)?,
+        None => None,
+    };
+    if erased.is_none() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Data erased</title>
+</head>
+<body>
+    <p>Your data has been erased.</p>
+</body>
+</html>"#,
+    ))
+}
+
+/// Return id of the subscriber the `data_request_token` of `kind` was issued for,
+/// if it hasn't expired.
+#[tracing::instrument(
+    name = "Get subscriber_id from data request token",
+    skip(data_request_token, pool)
+)]
+async fn get_subscriber_id_from_data_request_token(
+    pool: &PgPool,
+    data_request_token: &str,
+    kind: DataRequestKind,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscriber_id
+        FROM data_request_tokens
+        WHERE data_request_token = $1 AND kind = $2 AND expires_at > now()
+        "#,
+        data_request_token,
+        kind.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch a data request token.")?;
+
+    Ok(row.map(|r| r.subscriber_id))
+}
+
+/// Send an email with the link to access or erase their data to the subscriber.
+#[tracing::instrument(
+    name = "Send a data request email",
+    skip(email_client, email, base_url, data_request_token, unsubscribe_token)
+)]
+async fn send_data_request_email(
+    email_client: &EmailClient,
+    email: SubscriberEmail,
+    kind: DataRequestKind,
+    base_url: &str,
+    data_request_token: &str,
+    unsubscribe_token: &str,
+) -> Result<(), anyhow::Error> {
+    let (subject, path, action) = match kind {
+        DataRequestKind::Access => (
+            "Your data",
+            "data",
+            "download everything we store about you",
+        ),
+        DataRequestKind::Erasure => ("Erase your data", "erase", "erase your data"),
+    };
+    let link = format!(
+        "{}/subscriptions/{}?data_request_token={}",
+        base_url, path, data_request_token
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to {}.<br />\
+        The link is valid for {} hours. If you didn't ask for it, ignore this email.",
+        link, action, DATA_REQUEST_TOKEN_LIFETIME_HOURS
+    );
+    let text_body = format!(
+        "Visit {} to {}.\n\
+        The link is valid for {} hours. If you didn't ask for it, ignore this email.",
+        link, action, DATA_REQUEST_TOKEN_LIFETIME_HOURS
+    );
+
+    email_client
+        .send_email(
+            email,
+            subject,
+            &html_body,
+            &text_body,
+            &unsubscribe_link(base_url, unsubscribe_token),
+        )
+        .await
+}
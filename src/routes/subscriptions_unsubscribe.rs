@@ -0,0 +1,76 @@
+//!
+//! Contains `/subscriptions/unsubscribe` endpoint handlers.
+//!
+use crate::routes::delete_tokens;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Query parameters shape for `unsubscribe` endpoint.
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+/// Return the unsubscribe link for the subscriber owning `unsubscribe_token`.
+pub fn unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        base_url, unsubscribe_token
+    )
+}
+
+/// Mark the subscriber owning the token as unsubscribed. The row is kept, so that we
+/// remember not to email this address again.
+// Registered for both `GET` (link in the email body) and `POST` (RFC 8058 one-click
+// unsubscribe, sent by mail clients with `List-Unsubscribe=One-Click` form body, which
+// we don't need to inspect).
+//
+// Return `401 UNAUTHORIZED` if the token is unknown.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match mark_subscriber_as_unsubscribed(&pool, &parameters.unsubscribe_token).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::Unauthorized().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Set `unsubscribed` status for the subscriber owning `unsubscribe_token`, and delete their
+/// subscription tokens, so that an old confirmation link can't subscribe them again.
+/// Return `false` if there is no such subscriber.
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(unsubscribe_token, pool)
+)]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE unsubscribe_token = $1
+        RETURNING id
+        "#,
+        unsubscribe_token,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let subscriber_id = match row {
+        Some(row) => row.id,
+        None => return Ok(false),
+    };
+    delete_tokens(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(true)
+}
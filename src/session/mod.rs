@@ -0,0 +1,5 @@
+mod store;
+mod typed_session;
+
+pub use store::PostgresSessionStore;
+pub use typed_session::{delete_user_sessions, TypedSession};
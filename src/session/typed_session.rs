@@ -0,0 +1,62 @@
+//! Contains `TypedSession`, a typed interface to the session state.
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use sqlx::{Postgres, Transaction};
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+/// Wraps `Session`, so that the rest of the application doesn't deal with string keys.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+
+    /// Give the session a new key, to prevent session fixation attacks.
+    /// Call it whenever the privilege level changes (e.g. on login).
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    /// Remove the session state, both client-side and server-side.
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+}
+
+/// Delete every stored session of the user, logging them out on all devices.
+// `actix-session` stores values JSON-encoded, as `TypedSession::insert_user_id` does.
+#[tracing::instrument(name = "Delete user sessions", skip(transaction))]
+pub async fn delete_user_sessions(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let encoded_user_id = serde_json::to_string(&user_id).expect("Failed to encode a user id.");
+    sqlx::query!(
+        r#"DELETE FROM sessions WHERE state ->> $1 = $2"#,
+        TypedSession::USER_ID_KEY,
+        encoded_user_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+impl FromRequest for TypedSession {
+    // Return the same error returned by the implementation of `FromRequest` for `Session`.
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
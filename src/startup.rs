@@ -1,38 +1,66 @@
 //! Contains `build()` and `run()` functions used to create HTTP `Server` instance.
+use crate::authentication::{bootstrap_admin, reject_anonymous_users};
 use crate::configuration::{DatabaseSettings, Settings};
 use crate::database::configure_db_if_not_exists;
 use crate::email_client::EmailClient;
-use crate::routes::{health_check, subscribe};
+use crate::flash::FlashMessagesMiddleware;
+use crate::issue_delivery_worker::IssueDeliveryWorker;
+use crate::problem_details::{form_error_handler, query_error_handler};
+use crate::routes::{
+    add_subscriber, admin_dashboard, admin_subscribers, admin_subscribers_export, api_keys,
+    autosave_draft, cancel_newsletter, cancel_newsletter_from_form, change_email, change_password,
+    change_password_form, change_user_role, confirm, create_api_key, create_draft, draft_diff,
+    draft_form, drafts, email_form, erase, erasure_form, get_subscribers, health_check,
+    import_subscribers, invite_user, log_out, login, login_form, preferences_form,
+    publish_newsletter, publish_newsletter_form, publish_newsletter_from_form, remove_user,
+    request_subscriber_data, reschedule_newsletter, reschedule_newsletter_from_form,
+    revoke_api_key, save_draft, save_preferences, send_test_draft, subscribe, subscriber_data,
+    unsubscribe, users,
+};
+use crate::session::PostgresSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
+use actix_web_lab::middleware::from_fn;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 
-/// Type to hold the newly built server and its port
+/// Type to hold the newly built server, its port and the issue delivery worker
 pub struct Application {
     port: u16,
     server: Server,
+    worker: IssueDeliveryWorker,
 }
 
 impl Application {
-    /// Configure database, create DB connection pool, create the server.
+    /// Configure database, create DB connection pool, create the server and the worker.
+    /// Emails are sent with the transport selected in configuration.
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        let email_client = configuration.email_client.clone().client();
+        Self::build_with_email_client(configuration, email_client).await
+    }
+
+    /// Same as `build`, but send emails with the provided client
+    /// (e.g. backed by `InMemoryTransport` in tests).
+    pub async fn build_with_email_client(
+        configuration: Settings,
+        email_client: EmailClient,
+    ) -> Result<Self, std::io::Error> {
         configure_db_if_not_exists(&configuration.database).await;
 
         let connection_pool = get_connection_pool(&configuration.database);
-
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
+        bootstrap_admin(&connection_pool, &configuration.admin)
+            .await
+            .expect("Failed to create the first admin user.");
+        let worker = IssueDeliveryWorker::new(
+            connection_pool.clone(),
+            email_client.clone(),
+            configuration.application.base_url.clone(),
+            configuration.delivery_worker,
         );
 
         let address = format!(
@@ -41,20 +69,39 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
-        let server = run(listener, connection_pool, email_client)?;
+        let server = run(
+            listener,
+            connection_pool,
+            email_client,
+            configuration.application.base_url,
+            configuration.application.hmac_secret,
+            configuration.application.flash_secret,
+        )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            worker,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Run the server and the issue delivery worker, until either of them stops.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        tokio::select! {
+            outcome = self.server => outcome,
+            outcome = self.worker.run_until_stopped() => outcome,
+        }
     }
 }
 
+/// Wrapper type for the application base URL, so that it can be retrieved from application state
+/// (`actix-web` resolves `web::Data` by type, and a bare `String` would be ambiguous).
+pub struct ApplicationBaseUrl(pub String);
+
 /// Helper function to create DB connection pool.
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
@@ -63,15 +110,24 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 }
 
 /// Initialize and return HTTP `Server` instance, with `TracingLogger`, routes, database
-/// connection pool and email client attached to it.
+/// connection pool, email client and application base URL attached to it.
+/// Sessions are stored in the database, and their cookies are encrypted with `hmac_secret`.
+/// Flash messages are carried by cookies signed with `flash_secret`.
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    flash_secret: Secret<String>,
 ) -> Result<Server, std::io::Error> {
+    let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let flash_key = Key::from(flash_secret.expose_secret().as_bytes());
+    let session_store = PostgresSessionStore::new(db_pool.clone());
     // Wrap the connection pool and email client in smarts pointers (because we want them to be available for all workers).
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
     // `HttpServer::new` does not take `App` as argument - it wants a closure that returns an `App` struct.
     // This is to support actix-web’s runtime model: actix-web will spin up a worker process for each
     // available core on your machine.
@@ -80,12 +136,106 @@ pub fn run(
     // that `HttpServer::new` takes as argument.
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(FlashMessagesMiddleware::new(flash_key.clone()))
+            .wrap(SessionMiddleware::new(
+                session_store.clone(),
+                secret_key.clone(),
+            ))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/drafts", web::get().to(drafts))
+                    .route("/drafts", web::post().to(create_draft))
+                    .route("/drafts/{draft_id}", web::get().to(draft_form))
+                    .route("/drafts/{draft_id}", web::post().to(save_draft))
+                    .route(
+                        "/drafts/{draft_id}/autosave",
+                        web::post().to(autosave_draft),
+                    )
+                    .route("/drafts/{draft_id}/diff", web::get().to(draft_diff))
+                    .route("/drafts/{draft_id}/test", web::post().to(send_test_draft))
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route("/newsletters", web::post().to(publish_newsletter_from_form))
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/reschedule",
+                        web::post().to(reschedule_newsletter_from_form),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/cancel",
+                        web::post().to(cancel_newsletter_from_form),
+                    )
+                    .route("/email", web::get().to(email_form))
+                    .route("/email", web::post().to(change_email))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password))
+                    .route("/api_keys", web::get().to(api_keys))
+                    .route("/api_keys", web::post().to(create_api_key))
+                    .route("/api_keys/{key_id}/revoke", web::post().to(revoke_api_key))
+                    .service(
+                        web::resource("/subscribers")
+                            .app_data(
+                                web::QueryConfig::default().error_handler(query_error_handler),
+                            )
+                            .route(web::get().to(admin_subscribers)),
+                    )
+                    .service(
+                        web::resource("/subscribers/export")
+                            .app_data(
+                                web::QueryConfig::default().error_handler(query_error_handler),
+                            )
+                            .route(web::get().to(admin_subscribers_export)),
+                    )
+                    .service(
+                        web::resource("/subscribers/import")
+                            .app_data(
+                                web::QueryConfig::default().error_handler(query_error_handler),
+                            )
+                            .route(web::post().to(import_subscribers)),
+                    )
+                    .route("/users", web::get().to(users))
+                    .route("/users", web::post().to(invite_user))
+                    .route("/users/{user_id}/role", web::post().to(change_user_role))
+                    .route("/users/{user_id}/delete", web::post().to(remove_user))
+                    .route("/logout", web::post().to(log_out)),
+            )
+            .service(
+                web::resource("/api/subscribers")
+                    .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+                    .route(web::get().to(get_subscribers))
+                    .route(web::post().to(add_subscriber)),
+            )
             .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions/confirm", web::get().to(confirm))
+            .service(
+                web::resource("/subscriptions/data_requests")
+                    .app_data(web::FormConfig::default().error_handler(form_error_handler))
+                    .route(web::post().to(request_subscriber_data)),
+            )
+            .route("/subscriptions/data", web::get().to(subscriber_data))
+            .route("/subscriptions/erase", web::get().to(erasure_form))
+            .route("/subscriptions/erase", web::post().to(erase))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(save_preferences))
+            .route("/newsletters", web::post().to(publish_newsletter))
+            .route(
+                "/newsletters/{newsletter_issue_id}/reschedule",
+                web::post().to(reschedule_newsletter),
+            )
+            .route(
+                "/newsletters/{newsletter_issue_id}/cancel",
+                web::post().to(cancel_newsletter),
+            )
             // Register the connection pool as part of the application state
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(base_url.clone())
     })
     .listen(listener)?
     .run();
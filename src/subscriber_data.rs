@@ -0,0 +1,278 @@
+//! Contains access to and erasure of everything stored about a subscriber, as the GDPR
+//! entitles them to.
+//!
+//! Issues are deleted from `issue_delivery_queue` once delivered, and nothing tracks opens
+//! or clicks: there is no delivery history beyond the pending deliveries.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// What a subscriber asks for, by following the link emailed to them.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DataRequestKind {
+    /// Get a copy of their data.
+    Access,
+    /// Delete their data.
+    Erasure,
+}
+
+impl DataRequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Access => "access",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+}
+
+/// Everything stored about a subscriber.
+#[derive(serde::Serialize)]
+pub struct SubscriberData {
+    pub subscription: Subscription,
+    /// Slugs of the lists the subscriber gets.
+    pub lists: Vec<String>,
+    /// Tokens of the links confirming the subscription.
+    pub subscription_tokens: Vec<String>,
+    pub data_requests: Vec<DataRequest>,
+    /// Issues not delivered to the subscriber yet.
+    pub pending_deliveries: Vec<PendingDelivery>,
+}
+
+/// Subscription row.
+#[derive(serde::Serialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub unsubscribe_token: String,
+    pub delivery_frequency: String,
+    pub time_zone: Option<String>,
+}
+
+/// Link to access or erase the data, sent to the subscriber.
+#[derive(serde::Serialize)]
+pub struct DataRequest {
+    pub data_request_token: String,
+    pub kind: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issue waiting in the delivery queue.
+#[derive(serde::Serialize)]
+pub struct PendingDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub n_retries: i16,
+    pub execute_after: DateTime<Utc>,
+}
+
+/// Return everything stored about the subscriber with `subscriber_id`,
+/// or `None` if there is no such subscriber.
+#[tracing::instrument(name = "Get subscriber data", skip(pool))]
+pub async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberData>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscription = sqlx::query_as!(
+        Subscription,
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, confirmed_at, unsubscribe_token,
+            delivery_frequency, time_zone
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to fetch the subscription.")?;
+    let subscription = match subscription {
+        Some(subscription) => subscription,
+        None => return Ok(None),
+    };
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM subscription_lists sl
+        JOIN lists l ON l.list_id = sl.list_id
+        WHERE sl.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch lists.")?
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    let subscription_tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch subscription tokens.")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+    let data_requests = sqlx::query_as!(
+        DataRequest,
+        r#"
+        SELECT data_request_token, kind, expires_at
+        FROM data_request_tokens
+        WHERE subscriber_id = $1
+        ORDER BY expires_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch data request tokens.")?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY i.published_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to fetch pending deliveries.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to get subscriber data.")?;
+
+    Ok(Some(SubscriberData {
+        subscription,
+        lists,
+        subscription_tokens,
+        data_requests,
+        pending_deliveries,
+    }))
+}
+
+/// Delete every row about the subscriber with `subscriber_id`, redact their email in saved
+/// publish reports, and keep an audit record of the erasure. Return the id of the record,
+/// or `None` if there is no such subscriber.
+// Rows are deleted rather than pseudonymised: statistics don't need them, and the email
+// has to be forgotten anyway for the subscriber to be able to sign up again.
+#[tracing::instrument(name = "Erase subscriber", skip(pool))]
+pub async fn erase_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to lock the subscription.")?;
+    let email = match subscriber {
+        Some(subscriber) => subscriber.email,
+        None => return Ok(None),
+    };
+
+    let subscription_lists = sqlx::query!(
+        r#"DELETE FROM subscription_lists WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete list memberships.")?
+    .rows_affected();
+    let subscription_tokens = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete subscription tokens.")?
+    .rows_affected();
+    let data_request_tokens = sqlx::query!(
+        r#"DELETE FROM data_request_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete data request tokens.")?
+    .rows_affected();
+    let issue_delivery_queue = sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete pending deliveries.")?
+    .rows_affected();
+    // Publish reports list the subscribers skipped because of their stored email, and are
+    // kept to replay idempotent requests: redact the email in them.
+    let skipped_email = format!(r#""email":{}"#, serde_json::Value::from(email.as_str()));
+    let idempotency = sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET response_body = convert_to(
+            replace(convert_from(response_body, 'UTF8'), $1, '"email":"[erased]"'),
+            'UTF8'
+        )
+        WHERE position(convert_to($1, 'UTF8') IN response_body) > 0
+        "#,
+        skipped_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to redact saved publish reports.")?
+    .rows_affected();
+    let subscriptions = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscription.")?
+        .rows_affected();
+
+    let erasure_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_erasures (erasure_id, erased_at, deleted_rows)
+        VALUES ($1, now(), $2)
+        "#,
+        erasure_id,
+        serde_json::json!({
+            "subscriptions": subscriptions,
+            "subscription_lists": subscription_lists,
+            "subscription_tokens": subscription_tokens,
+            "data_request_tokens": data_request_tokens,
+            "issue_delivery_queue": issue_delivery_queue,
+            "idempotency": idempotency,
+        })
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the audit record of an erasure.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+
+    tracing::info!(%erasure_id, "Erased a subscriber.");
+    Ok(Some(erasure_id))
+}
@@ -0,0 +1,190 @@
+//! Contains `export_subscribers`, streaming all subscribers as CSV or NDJSON.
+use crate::csv::write_record;
+use actix_web::web::Bytes;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{Stream, TryStreamExt};
+use sqlx::PgPool;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Size above which the export is handed over as a chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Number of chunks which can be ready before the consumer takes them.
+const CHUNKS_AHEAD: usize = 4;
+
+/// Format of an export.
+#[derive(serde::Deserialize, clap::ValueEnum, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header row.
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// Value of `Content-Type` header for exports in this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// File name extension for exports in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Subscriber as exported.
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    /// `None` if the subscriber hasn't confirmed, or confirmed before it was recorded.
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// Return all subscribers in `format`, ordered by signup time, as a stream of chunks.
+// Rows are read from a single query as the consumer takes the chunks, so that neither the
+// table nor the export sit in memory. The stream fails if the query does: the consumer gets
+// a truncated export, which it must discard.
+pub fn export_subscribers(
+    pool: PgPool,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, anyhow::Error>> {
+    let (sender, receiver) = mpsc::channel(CHUNKS_AHEAD);
+    tokio::spawn(
+        async move {
+            if let Err(e) = write_export(&pool, format, &sender).await {
+                tracing::error!(error.cause_chain = ?e, "Failed to export subscribers.");
+                let _ = sender.send(Err(e)).await;
+            }
+        }
+        .instrument(tracing::info_span!("Export subscribers", ?format)),
+    );
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+/// Write all subscribers in `format` to `writer`, e.g. a file.
+pub async fn export_subscribers_to<W>(
+    pool: PgPool,
+    format: ExportFormat,
+    mut writer: W,
+) -> Result<(), anyhow::Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let chunks = export_subscribers(pool, format);
+    futures_util::pin_mut!(chunks);
+    while let Some(chunk) = chunks.try_next().await? {
+        writer
+            .write_all(&chunk)
+            .await
+            .context("Failed to write the export.")?;
+    }
+    writer
+        .flush()
+        .await
+        .context("Failed to write the export.")?;
+    Ok(())
+}
+
+/// Send the export to `sender` chunk by chunk. Stop early if the receiver is gone.
+async fn write_export(
+    pool: &PgPool,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<Bytes, anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE);
+    if format == ExportFormat::Csv {
+        write_record(
+            &mut buffer,
+            &[
+                "id",
+                "email",
+                "name",
+                "status",
+                "subscribed_at",
+                "confirmed_at",
+            ],
+        );
+    }
+    let mut rows = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at
+        FROM subscriptions
+        ORDER BY subscribed_at, id
+        "#
+    )
+    .fetch(pool);
+    let mut exported = 0;
+    while let Some(subscriber) = rows
+        .try_next()
+        .await
+        .context("Failed to fetch subscribers to export.")?
+    {
+        write_subscriber(&mut buffer, format, &subscriber)?;
+        exported += 1;
+        if buffer.len() >= CHUNK_SIZE {
+            let chunk = std::mem::replace(&mut buffer, Vec::with_capacity(CHUNK_SIZE));
+            if sender.send(Ok(chunk.into())).await.is_err() {
+                tracing::info!("The export was abandoned.");
+                return Ok(());
+            }
+        }
+    }
+    if !buffer.is_empty() {
+        let _ = sender.send(Ok(buffer.into())).await;
+    }
+    tracing::info!(exported, "Exported subscribers.");
+    Ok(())
+}
+
+/// Append `subscriber` to `buffer` in `format`.
+fn write_subscriber(
+    buffer: &mut Vec<u8>,
+    format: ExportFormat,
+    subscriber: &ExportedSubscriber,
+) -> Result<(), anyhow::Error> {
+    match format {
+        ExportFormat::Csv => {
+            let timestamp = |t: &DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Micros, true);
+            write_record(
+                buffer,
+                &[
+                    &subscriber.id.to_string(),
+                    &subscriber.email,
+                    &subscriber.name,
+                    &subscriber.status,
+                    &timestamp(&subscriber.subscribed_at),
+                    &subscriber
+                        .confirmed_at
+                        .as_ref()
+                        .map(timestamp)
+                        .unwrap_or_default(),
+                ],
+            );
+        }
+        ExportFormat::Ndjson => {
+            serde_json::to_writer(&mut *buffer, subscriber)
+                .context("Failed to serialize a subscriber.")?;
+            buffer.push(b'\n');
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+//! Contains `SubscriberImport`, storing subscribers listed in a CSV file, e.g. exported
+//! from a previous newsletter provider.
+//!
+//! The file must start with a header row with `email` and `name` columns, in any order;
+//! other columns are ignored. Imported subscribers have already confirmed their subscription
+//! with the previous provider: they are stored as `confirmed`, subscribed to every list, without a confirmation email.
+use crate::csv::{CsvReader, CsvRecord};
+use crate::domain::{NewSubscriber, NewSubscriberError, SubscriberEmail, SubscriberName};
+use crate::problem_details::ProblemDetails;
+use crate::utils::{error_chain_fmt, generate_token};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+/// Number of subscribers stored with a single `INSERT`.
+const BATCH_SIZE: usize = 1000;
+/// Size of the chunks read from CSV files.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Outcome of an import, row by row. Rows are numbered from 1, the header row included,
+/// as spreadsheets do.
+#[derive(serde::Serialize, Debug)]
+pub struct ImportReport {
+    /// If `true`, nothing has been stored: the report tells what an actual import would do.
+    pub dry_run: bool,
+    /// Number of new subscribers.
+    pub imported: usize,
+    pub rejected: Vec<RejectedRow>,
+    pub duplicates: Vec<DuplicateRow>,
+}
+
+/// Row which can't be imported because it's invalid.
+#[derive(serde::Serialize, Debug)]
+pub struct RejectedRow {
+    pub row: u64,
+    pub reason: String,
+}
+
+/// Row skipped because its email is already on the list, or on a previous row.
+#[derive(serde::Serialize, Debug)]
+pub struct DuplicateRow {
+    pub row: u64,
+    pub email: String,
+}
+
+/// Failure of an import as a whole.
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("The file must start with a header row with `email` and `name` columns.")]
+    MissingColumns,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImportError::MissingColumns => StatusCode::BAD_REQUEST,
+            ImportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ImportError::MissingColumns => ProblemDetails::new(self.status_code())
+                .detail(self.to_string())
+                .response(),
+            ImportError::UnexpectedError(_) => ProblemDetails::new(self.status_code()).response(),
+        }
+    }
+}
+
+/// Positions of the columns we need, as given by the header row.
+#[derive(Debug)]
+struct Columns {
+    email: usize,
+    name: usize,
+}
+
+/// Valid row waiting to be stored with the next batch.
+struct ValidRow {
+    row: u64,
+    subscriber: NewSubscriber,
+}
+
+/// Import in progress. Feed it the CSV file chunk by chunk, then call `finish` to get the report.
+// Rows are validated as they arrive and stored by batches, so that large files neither sit in
+// memory nor cost a round trip to the database per row. Each batch is committed on its own:
+// if an import fails halfway, running it again skips the rows already stored as duplicates.
+pub struct SubscriberImport<'a> {
+    pool: &'a PgPool,
+    dry_run: bool,
+    reader: CsvReader,
+    columns: Option<Columns>,
+    rows: u64,
+    /// Emails of the valid rows so far, to detect duplicates within the file.
+    seen_emails: HashSet<String>,
+    batch: Vec<ValidRow>,
+    report: ImportReport,
+}
+
+impl<'a> SubscriberImport<'a> {
+    /// Start an import. If `dry_run` is `true`, the file is checked but nothing is stored.
+    pub fn new(pool: &'a PgPool, dry_run: bool) -> Self {
+        Self {
+            pool,
+            dry_run,
+            reader: CsvReader::default(),
+            columns: None,
+            rows: 0,
+            seen_emails: HashSet::new(),
+            batch: Vec::with_capacity(BATCH_SIZE),
+            report: ImportReport {
+                dry_run,
+                imported: 0,
+                rejected: vec![],
+                duplicates: vec![],
+            },
+        }
+    }
+
+    /// Process the next `chunk` of the file.
+    pub async fn feed(&mut self, chunk: &[u8]) -> Result<(), ImportError> {
+        for record in self.reader.feed(chunk) {
+            self.process(record).await?;
+        }
+        Ok(())
+    }
+
+    /// Process the end of the file, and return the report.
+    #[tracing::instrument(name = "Finish subscriber import", skip_all, fields(dry_run = self.dry_run))]
+    pub async fn finish(mut self) -> Result<ImportReport, ImportError> {
+        for record in self.reader.finish() {
+            self.process(record).await?;
+        }
+        if self.columns.is_none() {
+            return Err(ImportError::MissingColumns);
+        }
+        self.store_batch().await?;
+        tracing::info!(
+            imported = self.report.imported,
+            rejected = self.report.rejected.len(),
+            duplicates = self.report.duplicates.len(),
+            "Imported subscribers."
+        );
+        Ok(self.report)
+    }
+
+    async fn process(&mut self, record: CsvRecord) -> Result<(), ImportError> {
+        self.rows += 1;
+        let row = self.rows;
+        let columns = match &self.columns {
+            Some(columns) => columns,
+            None => {
+                let header = record.map_err(|_| ImportError::MissingColumns)?;
+                self.columns = Some(parse_header(&header).ok_or(ImportError::MissingColumns)?);
+                return Ok(());
+            }
+        };
+        let subscriber = match record {
+            Ok(fields) => {
+                parse_row(&fields, columns).map_err(|e| format!("{} {}", e.field(), e.reason()))
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match subscriber {
+            Ok(subscriber) => {
+                if self
+                    .seen_emails
+                    .insert(subscriber.email.as_ref().to_owned())
+                {
+                    self.batch.push(ValidRow { row, subscriber });
+                    if self.batch.len() >= BATCH_SIZE {
+                        self.store_batch().await?;
+                    }
+                } else {
+                    self.report.duplicates.push(DuplicateRow {
+                        row,
+                        email: subscriber.email.as_ref().to_owned(),
+                    });
+                }
+            }
+            Err(reason) => self.report.rejected.push(RejectedRow { row, reason }),
+        }
+        Ok(())
+    }
+
+    /// Store the subscribers of the current batch, or only look for duplicates in a dry run,
+    /// and report the outcome.
+    async fn store_batch(&mut self) -> Result<(), anyhow::Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let new_emails = if self.dry_run {
+            find_new_emails(self.pool, &batch).await
+        } else {
+            insert_subscribers(self.pool, &batch).await
+        }
+        .context("Failed to store a batch of imported subscribers.")?;
+        for valid_row in batch {
+            if new_emails.contains(valid_row.subscriber.email.as_ref()) {
+                self.report.imported += 1;
+            } else {
+                self.report.duplicates.push(DuplicateRow {
+                    row: valid_row.row,
+                    email: valid_row.subscriber.email.as_ref().to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Import subscribers from the CSV file at `path`, reading it chunk by chunk.
+#[tracing::instrument(name = "Import subscribers from file", skip(pool))]
+pub async fn import_subscribers_from_file(
+    pool: &PgPool,
+    path: &Path,
+    dry_run: bool,
+) -> Result<ImportReport, ImportError> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}.", path.display()))?;
+    let mut import = SubscriberImport::new(pool, dry_run);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut chunk)
+            .await
+            .with_context(|| format!("Failed to read {}.", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        import.feed(&chunk[..read]).await?;
+    }
+    import.finish().await
+}
+
+/// Return the positions of `email` and `name` columns, or `None` if one is missing.
+fn parse_header(header: &[String]) -> Option<Columns> {
+    let position = |column: &str| {
+        header.iter().position(|field| {
+            // Spreadsheets often start their CSV exports with a byte order mark.
+            field
+                .trim_start_matches('\u{feff}')
+                .trim()
+                .eq_ignore_ascii_case(column)
+        })
+    };
+    Some(Columns {
+        email: position("email")?,
+        name: position("name")?,
+    })
+}
+
+/// Validate a row as `NewSubscriber`. Missing fields are taken as empty.
+fn parse_row(fields: &[String], columns: &Columns) -> Result<NewSubscriber, NewSubscriberError> {
+    let field = |index: usize| {
+        fields
+            .get(index)
+            .map(|f| f.trim().to_owned())
+            .unwrap_or_default()
+    };
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(field(columns.email))?,
+        name: SubscriberName::parse(field(columns.name))?,
+    })
+}
+
+/// Store subscribers of `batch` as `confirmed`, subscribed to every list, skipping emails
+/// which are already stored. Return the emails of the subscribers actually stored.
+#[tracing::instrument(name = "Insert imported subscribers", skip_all, fields(rows = batch.len()))]
+async fn insert_subscribers(
+    pool: &PgPool,
+    batch: &[ValidRow],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|r| r.subscriber.email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = batch
+        .iter()
+        .map(|r| r.subscriber.name.as_ref().to_owned())
+        .collect();
+    let tokens: Vec<String> = batch.iter().map(|_| generate_token()).collect();
+    let rows = sqlx::query!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            SELECT id, email, name, now(), 'confirmed', unsubscribe_token
+            FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[])
+                AS imported (id, email, name, unsubscribe_token)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING id, email
+        ), memberships AS (
+            INSERT INTO subscription_lists (subscriber_id, list_id)
+            SELECT inserted.id, lists.list_id FROM inserted CROSS JOIN lists
+        )
+        SELECT email AS "email!" FROM inserted
+        "#,
+        &ids,
+        &emails,
+        &names,
+        &tokens
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.email).collect())
+}
+
+/// Return the emails of `batch` which aren't stored yet.
+#[tracing::instrument(name = "Find new imported emails", skip_all, fields(rows = batch.len()))]
+async fn find_new_emails(
+    pool: &PgPool,
+    batch: &[ValidRow],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|r| r.subscriber.email.as_ref().to_owned())
+        .collect();
+    let stored = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE email = ANY($1)"#,
+        &emails
+    )
+    .fetch_all(pool)
+    .await?;
+    let stored: HashSet<String> = stored.into_iter().map(|r| r.email).collect();
+    Ok(emails.into_iter().filter(|e| !stored.contains(e)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_header, parse_row, Columns};
+    use claim::{assert_err, assert_none, assert_ok, assert_some};
+
+    fn strings(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn header_columns_are_found_in_any_order_and_case() {
+        let columns = assert_some!(parse_header(&strings(&["\u{feff}Name", "id", " EMAIL "])));
+
+        assert_eq!((columns.email, columns.name), (2, 0));
+    }
+
+    #[test]
+    fn header_without_email_or_name_is_rejected() {
+        assert_none!(parse_header(&strings(&["email", "full_name"])));
+        assert_none!(parse_header(&strings(&["mail", "name"])));
+    }
+
+    #[test]
+    fn rows_are_trimmed_and_validated() {
+        let columns = Columns { email: 0, name: 1 };
+
+        let subscriber = assert_ok!(parse_row(&strings(&[" a@example.com ", "Ann"]), &columns));
+        assert_eq!(subscriber.email.as_ref(), "a@example.com");
+        let error = assert_err!(parse_row(&strings(&["not-an-email", "Ann"]), &columns));
+        assert_eq!(error.field(), "email");
+        let error = assert_err!(parse_row(&strings(&["a@example.com"]), &columns));
+        assert_eq!(error.field(), "name");
+    }
+}
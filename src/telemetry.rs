@@ -1,4 +1,5 @@
 //! Functions related to application state tracing.
+use tokio::task::JoinHandle;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -38,3 +39,14 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // Specify that our `subscriber` should be used to process spans
     set_global_default(subscriber).expect("Failed to set subscriber.");
 }
+
+/// Run CPU-intensive `f` on tokio's blocking thread pool, so that it doesn't stall
+/// the async workers, keeping the current span as its parent.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
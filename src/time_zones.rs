@@ -0,0 +1,43 @@
+//! Contains validation of the IANA time zones subscribers live in, e.g. `Europe/Berlin`.
+//!
+//! Postgres does the time zone arithmetic, with its own time zone database: names are checked
+//! against `pg_timezone_names`, so that every stored name can be used with `AT TIME ZONE`.
+use sqlx::PgPool;
+
+/// Time zone which reaches any local time first, UTC+14. Its POSIX name has the inverted sign.
+pub const EARLIEST_TIME_ZONE: &str = "Etc/GMT-14";
+
+/// Name which isn't a known time zone.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("{0} is not a known time zone.")]
+pub struct UnknownTimeZoneError(pub String);
+
+/// Failure to parse a time zone.
+#[derive(thiserror::Error, Debug)]
+pub enum TimeZoneError {
+    #[error(transparent)]
+    UnknownTimeZone(#[from] UnknownTimeZoneError),
+    #[error("Failed to look the time zone up.")]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+/// Return the time zone named by `time_zone`, `None` if it is blank.
+pub async fn parse_time_zone(
+    pool: &PgPool,
+    time_zone: &str,
+) -> Result<Option<String>, TimeZoneError> {
+    let time_zone = time_zone.trim();
+    if time_zone.is_empty() {
+        return Ok(None);
+    }
+    let row = sqlx::query!(
+        r#"SELECT name AS "name!" FROM pg_timezone_names WHERE name = $1"#,
+        time_zone
+    )
+    .fetch_optional(pool)
+    .await?;
+    match row {
+        Some(row) => Ok(Some(row.name)),
+        None => Err(UnknownTimeZoneError(time_zone.to_owned()).into()),
+    }
+}
@@ -0,0 +1,52 @@
+//! Contains helpers shared by the rest of the application.
+use actix_web::http::header::LOCATION;
+use actix_web::HttpResponse;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// Format `e` followed by all of its causes, one per line, for `Debug` implementations
+/// of error types: the default one only shows the outermost error.
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Convert `e` into `400 BAD REQUEST`, preserving it for logging.
+pub fn e400<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorBadRequest(e)
+}
+
+/// Convert `e` into `500 INTERNAL SERVER ERROR`, preserving it for logging.
+pub fn e500<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorInternalServerError(e)
+}
+
+/// Return `303 SEE OTHER` response, redirecting the client to `location` with a `GET` request.
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Generate a random 25-characters-long case-sensitive token.
+pub fn generate_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
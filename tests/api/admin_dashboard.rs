@@ -0,0 +1,55 @@
+//! Contains tests for `/admin` endpoints: dashboard and logout.
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+/// Check that anonymous users are redirected to the login form.
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that anonymous users can't log out.
+#[tokio::test]
+async fn you_must_be_logged_in_to_log_out() {
+    let app = spawn_app().await;
+
+    let response = app.post_logout().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that logging out clears the session.
+#[tokio::test]
+async fn logout_clears_session_state() {
+    let app = spawn_app().await;
+    let response = app.login_as_admin().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that the session is deleted from the server-side store on logout.
+#[tokio::test]
+async fn logout_deletes_the_stored_session() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let count = || async {
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM sessions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count
+    };
+    assert_eq!(count().await, 1);
+
+    app.post_logout().await;
+
+    assert_eq!(count().await, 0);
+}
@@ -0,0 +1,183 @@
+//! Contains tests for `GET /admin/subscribers` and `GET /api/subscribers` endpoints.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+/// Return the time `day` days after June 1st, 2023.
+fn day(day: i64) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap() + chrono::Duration::days(day)
+}
+
+/// Store a subscriber named `name`, with `name@example.com` email, who signed up at `subscribed_at`.
+async fn insert_subscriber(app: &TestApp, name: &str, status: &str, subscribed_at: DateTime<Utc>) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        format!("{}@example.com", name),
+        name,
+        subscribed_at,
+        status,
+        Uuid::new_v4().to_string()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Get the subscribers page with `query` string as the logged in admin.
+async fn get_subscribers(app: &TestApp, query: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin/subscribers?{}", &app.address, query))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Return the names of listed subscribers, and the cursor of the next page.
+async fn names_and_cursor(response: reqwest::Response) -> (Vec<String>, Option<String>) {
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    let names = page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["name"].as_str().unwrap().to_owned())
+        .collect();
+    (names, page["next_cursor"].as_str().map(str::to_owned))
+}
+
+/// Check that anonymous users are redirected to the login form.
+#[tokio::test]
+async fn you_must_be_logged_in_to_list_subscribers() {
+    let app = spawn_app().await;
+
+    let response = get_subscribers(&app, "").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that all pages together list every subscriber once, newest first.
+#[tokio::test]
+async fn pages_list_every_subscriber_once() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    for i in 0..5 {
+        insert_subscriber(&app, &format!("user{}", i), "confirmed", day(i)).await;
+    }
+    // Same signup time as `user4`: the id breaks the tie.
+    insert_subscriber(&app, "user5", "confirmed", day(4)).await;
+
+    let mut names = Vec::new();
+    let mut cursor: Option<String> = None;
+    let mut pages = 0;
+    loop {
+        let query = match &cursor {
+            Some(cursor) => format!("limit=2&cursor={}", cursor),
+            None => "limit=2".to_owned(),
+        };
+        let (page, next_cursor) = names_and_cursor(get_subscribers(&app, &query).await).await;
+        names.extend(page);
+        pages += 1;
+        cursor = next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    assert_eq!(pages, 3);
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(
+        sorted,
+        vec!["user0", "user1", "user2", "user3", "user4", "user5"]
+    );
+    assert_eq!(&names[4..], ["user1", "user0"]);
+}
+
+/// Check that subscribers are filtered by status, signup time and search string.
+#[tokio::test]
+async fn subscribers_are_filtered() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    insert_subscriber(&app, "alice", "confirmed", day(0)).await;
+    insert_subscriber(&app, "bob", "pending_confirmation", day(1)).await;
+    insert_subscriber(&app, "carol", "unsubscribed", day(2)).await;
+    insert_subscriber(&app, "Alicia", "confirmed", day(3)).await;
+    let test_cases = vec![
+        ("status=confirmed", vec!["Alicia", "alice"]),
+        ("status=unsubscribed", vec!["carol"]),
+        (
+            "subscribed_after=2023-06-02T12:00:00Z&subscribed_before=2023-06-04T12:00:00Z",
+            vec!["carol", "bob"],
+        ),
+        ("search=ALI", vec!["Alicia", "alice"]),
+        ("search=%25", vec![]),
+        ("search=ali&order=asc", vec!["alice", "Alicia"]),
+    ];
+
+    for (query, expected) in test_cases {
+        let (names, cursor) = names_and_cursor(get_subscribers(&app, query).await).await;
+
+        assert_eq!(names, expected, "Unexpected subscribers for `{}`.", query);
+        assert_eq!(cursor, None);
+    }
+}
+
+/// Check that invalid query strings are rejected with a problem details body.
+#[tokio::test]
+async fn invalid_queries_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let test_cases = vec![
+        ("cursor=garbage", Some("cursor")),
+        ("limit=0", Some("limit")),
+        ("limit=501", Some("limit")),
+        ("status=deleted", None),
+        ("subscribed_after=yesterday", None),
+    ];
+
+    for (query, invalid_param) in test_cases {
+        let response = get_subscribers(&app, query).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "`{}` wasn't rejected.",
+            query
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        if let Some(param) = invalid_param {
+            assert_eq!(problem["invalid-params"][0]["name"], param);
+        }
+    }
+}
+
+/// Check that API keys with `subscribers:read` scope can list subscribers, and others can't.
+#[tokio::test]
+async fn api_keys_list_subscribers_with_read_scope() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    insert_subscriber(&app, "alice", "confirmed", day(0)).await;
+    let read_key = app.create_api_key("subscribers:read").await;
+    let write_key = app.create_api_key("subscribers:write").await;
+
+    let allowed = reqwest::Client::new()
+        .get(format!("{}/api/subscribers?limit=1", &app.address))
+        .bearer_auth(&read_key)
+        .send()
+        .await
+        .unwrap();
+    let forbidden = reqwest::Client::new()
+        .get(format!("{}/api/subscribers", &app.address))
+        .bearer_auth(&write_key)
+        .send()
+        .await
+        .unwrap();
+
+    let (names, _) = names_and_cursor(allowed).await;
+    assert_eq!(names, vec!["alice"]);
+    assert_eq!(forbidden.status().as_u16(), 403);
+}
@@ -0,0 +1,327 @@
+//! Contains tests for roles of admin users, and `/admin/users` endpoints managing them.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Return a client with its own cookie store, to act as another user than `app.api_client`.
+fn another_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+/// Log `client` in with `username` and `password`.
+async fn log_in(app: &TestApp, client: &reqwest::Client, username: &str, password: &str) {
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({"username": username, "password": password}))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+/// Return the id of the user with `username`.
+async fn user_id(app: &TestApp, username: &str) -> Uuid {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id
+}
+
+/// Publish an issue with Basic credentials.
+async fn publish_as(app: &TestApp, username: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Check that the first admin is an owner, and can see the users page.
+#[tokio::test]
+async fn first_admin_is_an_owner() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let dashboard = app.get_admin_dashboard_html().await;
+    let response = app
+        .api_client
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(dashboard.contains("Your role is owner."));
+    assert!(dashboard.contains(r#"<a href="/admin/users">Manage admins</a>"#));
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(&format!("<td>{}</td>", app.admin_username)));
+}
+
+/// Check that invited users can log in with their temporary password, with the given role.
+#[tokio::test]
+async fn invited_users_log_in_with_their_role() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let password = app.invite_user("editor", "editor").await;
+
+    let client = another_client();
+    log_in(&app, &client, "editor", &password).await;
+    let dashboard = client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(dashboard.contains("Your role is editor."));
+    assert!(!dashboard.contains("Manage admins"));
+}
+
+/// Check that invitations with a taken username or an unknown role are rejected.
+#[tokio::test]
+async fn invalid_invitations_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"username": &app.admin_username, "role": "editor"}),
+            "The username is already taken.",
+        ),
+        (
+            serde_json::json!({"username": "  ", "role": "editor"}),
+            "The username must be between 1 and 64 characters long.",
+        ),
+        (
+            serde_json::json!({"username": "someone", "role": "admin"}),
+            "`admin` is not a known role.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        let response = app.post_users(&body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(
+            response.text().await.unwrap().contains(error_message),
+            "The page didn't show \"{}\".",
+            error_message
+        );
+    }
+}
+
+/// Check that only owners can manage users.
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let password = app.invite_user("editor", "editor").await;
+    let owner_id = user_id(&app, &app.admin_username).await;
+    let client = another_client();
+    log_in(&app, &client, "editor", &password).await;
+
+    let list = client
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let invite = client
+        .post(format!("{}/admin/users", &app.address))
+        .form(&serde_json::json!({"username": "intruder", "role": "owner"}))
+        .send()
+        .await
+        .unwrap();
+    let demote = client
+        .post(format!("{}/admin/users/{}/role", &app.address, owner_id))
+        .form(&serde_json::json!({"role": "viewer"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(list.status().as_u16(), 403);
+    assert_eq!(invite.status().as_u16(), 403);
+    assert_eq!(demote.status().as_u16(), 403);
+    let owner = sqlx::query!("SELECT role FROM users WHERE user_id = $1", owner_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(owner.role, "owner");
+}
+
+/// Check that editors can publish issues, and viewers can't.
+#[tokio::test]
+async fn only_editors_and_owners_can_publish() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let editor_password = app.invite_user("editor", "editor").await;
+    let viewer_password = app.invite_user("viewer", "viewer").await;
+
+    let editor_response = publish_as(&app, "editor", &editor_password).await;
+    let viewer_response = publish_as(&app, "viewer", &viewer_password).await;
+
+    assert_eq!(editor_response.status().as_u16(), 200);
+    assert_eq!(viewer_response.status().as_u16(), 403);
+}
+
+/// Check that a demotion takes effect immediately, also for the user's API keys.
+#[tokio::test]
+async fn demotion_takes_effect_immediately() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let password = app.invite_user("editor", "editor").await;
+    let client = another_client();
+    log_in(&app, &client, "editor", &password).await;
+    let html_page = client
+        .post(format!("{}/admin/api_keys", &app.address))
+        .form(&serde_json::json!({"name": "CMS", "scopes": "newsletters:publish"}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let api_key = crate::helpers::extract_code(&html_page);
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/users/{}/role",
+            &app.address,
+            user_id(&app, "editor").await
+        ))
+        .form(&serde_json::json!({"role": "viewer"}))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/users");
+
+    assert_eq!(
+        publish_as(&app, "editor", &password)
+            .await
+            .status()
+            .as_u16(),
+        403
+    );
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .bearer_auth(&api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+/// Check that viewers can't create API keys with scopes their role doesn't permit.
+#[tokio::test]
+async fn api_key_scopes_are_limited_by_role() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let password = app.invite_user("viewer", "viewer").await;
+    let client = another_client();
+    log_in(&app, &client, "viewer", &password).await;
+
+    let response = client
+        .post(format!("{}/admin/api_keys", &app.address))
+        .form(&serde_json::json!({"name": "CMS", "scopes": "subscribers:read newsletters:publish"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The `viewer` role does not permit `newsletters:publish` scope."));
+}
+
+/// Check that the last owner can be neither demoted nor removed.
+#[tokio::test]
+async fn the_last_owner_cannot_be_demoted_or_removed() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let owner_id = user_id(&app, &app.admin_username).await;
+
+    let demote = app
+        .api_client
+        .post(format!("{}/admin/users/{}/role", &app.address, owner_id))
+        .form(&serde_json::json!({"role": "editor"}))
+        .send()
+        .await
+        .unwrap();
+    let remove = app
+        .api_client
+        .post(format!("{}/admin/users/{}/delete", &app.address, owner_id))
+        .send()
+        .await
+        .unwrap();
+
+    for response in [demote, remove] {
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("There must be at least one owner left."));
+    }
+    let owner = sqlx::query!("SELECT role FROM users WHERE user_id = $1", owner_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(owner.role, "owner");
+}
+
+/// Check that removed users are logged out, and can't log in anymore.
+#[tokio::test]
+async fn removed_users_are_logged_out() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let password = app.invite_user("editor", "editor").await;
+    let client = another_client();
+    log_in(&app, &client, "editor", &password).await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/users/{}/delete",
+            &app.address,
+            user_id(&app, "editor").await
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({"username": "editor", "password": &password}))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,206 @@
+//! Contains tests for API keys: `/admin/api_keys` endpoints and Bearer authentication.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Return valid newsletter issue JSON body.
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+/// Publish an issue authenticating with `api_key`.
+async fn publish_with_api_key(app: &TestApp, api_key: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .bearer_auth(api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Check that anonymous users can't manage API keys.
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_keys() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_keys(&serde_json::json!({
+            "name": "CMS",
+            "scopes": "newsletters:publish",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that a new key is shown once, and then listed without it.
+#[tokio::test]
+async fn new_api_key_is_shown_once_and_listed() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    assert!(api_key.starts_with("nl_"));
+    let html_page = app.get_api_keys_html().await;
+    assert!(html_page.contains("<td>Test client</td><td>newsletters:publish</td>"));
+    assert!(!html_page.contains(&api_key));
+}
+
+/// Check that keys without a name or with unknown scopes are rejected with `400 BAD REQUEST`.
+#[tokio::test]
+async fn invalid_api_key_forms_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "scopes": "newsletters:publish"}),
+            "The API key must have a name.",
+        ),
+        (
+            serde_json::json!({"name": "CMS", "scopes": ""}),
+            "The API key must have at least one scope.",
+        ),
+        (
+            serde_json::json!({"name": "CMS", "scopes": "everything"}),
+            "`everything` is not a known scope.",
+        ),
+        (
+            serde_json::json!({"name": "CMS", "scopes": "newsletters:publish", "expires_in_days": "0"}),
+            "The validity must be a positive number of days.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        let response = app.post_api_keys(&body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(
+            response.text().await.unwrap().contains(error_message),
+            "The page didn't show \"{}\".",
+            error_message
+        );
+    }
+}
+
+/// Check that a key with `newsletters:publish` scope can publish issues, and its use is recorded.
+#[tokio::test]
+async fn api_key_with_publish_scope_can_publish() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    let response = publish_with_api_key(&app, &api_key).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let key = sqlx::query!("SELECT last_used_at FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(key.last_used_at.is_some());
+}
+
+/// Check that a key without the scope of the route is rejected with `403 FORBIDDEN`.
+#[tokio::test]
+async fn api_key_without_the_required_scope_is_forbidden() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let api_key = app
+        .create_api_key("subscribers:read subscribers:write")
+        .await;
+
+    let response = publish_with_api_key(&app, &api_key).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Bearer error="insufficient_scope", scope="newsletters:publish""#
+    );
+}
+
+/// Check that unknown, revoked and expired keys are rejected with `401 UNAUTHORIZED`.
+#[tokio::test]
+async fn unknown_revoked_and_expired_api_keys_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let revoked_key = app.create_api_key("newsletters:publish").await;
+    let revoked_key_id = sqlx::query!("SELECT key_id FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .key_id;
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api_keys/{}/revoke",
+            &app.address, revoked_key_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/api_keys");
+    let expired_key = app.create_api_key("newsletters:publish").await;
+    sqlx::query!(
+        "UPDATE api_keys SET expires_at = now() - interval '1 day' WHERE key_id <> $1",
+        revoked_key_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    for api_key in [format!("nl_{}", Uuid::new_v4()), revoked_key, expired_key] {
+        let response = publish_with_api_key(&app, &api_key).await;
+
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Bearer error="invalid_token""#
+        );
+    }
+}
+
+/// Check that a key with `subscribers:write` scope can add subscribers, who still have to confirm.
+#[tokio::test]
+async fn api_key_with_write_scope_can_add_subscribers() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let api_key = app.create_api_key("subscribers:write").await;
+
+    let response = app
+        .post_api_subscribers(
+            &api_key,
+            serde_json::json!({"name": "hazadus", "email": "hazadus7@gmail.com"}),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "hazadus7@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(app.wait_for_messages(1).await.len(), 1);
+}
+
+/// Check that adding subscribers requires an API key with `subscribers:write` scope.
+#[tokio::test]
+async fn adding_subscribers_requires_write_scope() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+    let body = serde_json::json!({"name": "hazadus", "email": "hazadus7@gmail.com"});
+
+    let forbidden = app.post_api_subscribers(&api_key, body.clone()).await;
+    let unauthorized = app.post_api_subscribers("nl_unknown", body).await;
+
+    assert_eq!(forbidden.status().as_u16(), 403);
+    assert_eq!(unauthorized.status().as_u16(), 401);
+}
@@ -0,0 +1,181 @@
+//! Contains tests for `/admin/password` endpoints.
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use uuid::Uuid;
+
+/// Check that anonymous users are redirected to the login form.
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_change_password_form() {
+    let app = spawn_app().await;
+
+    let response = app.get_change_password().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that logged in users get the change password form.
+#[tokio::test]
+async fn change_password_form_is_served() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let html_page = app.get_change_password_html().await;
+
+    assert!(html_page.contains(r#"<form action="/admin/password" method="post">"#));
+    assert!(html_page.contains(r#"name="current_password""#));
+    assert!(html_page.contains(r#"name="new_password_check""#));
+}
+
+/// Check that anonymous users can't change passwords.
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that the new password must be typed twice the same way.
+#[tokio::test]
+async fn new_password_fields_must_match() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.admin_password,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+/// Check that the current password is required to change it.
+#[tokio::test]
+async fn current_password_must_be_valid() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+}
+
+/// Check that too short and too long new passwords are rejected.
+#[tokio::test]
+async fn new_password_length_is_checked() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let test_cases = vec![
+        ("a".repeat(11), "at least 12 characters"),
+        ("a".repeat(129), "at most 128 characters"),
+    ];
+
+    for (new_password, error_message) in test_cases {
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.admin_password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+            .await;
+
+        assert_is_redirect_to(&response, "/admin/password");
+        assert!(
+            app.get_change_password_html().await.contains(error_message),
+            "The form didn't mention \"{}\".",
+            error_message
+        );
+    }
+}
+
+/// Check that after changing the password only the new one lets the admin log in.
+#[tokio::test]
+async fn changing_password_works() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.admin_password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    app.post_logout().await;
+    let response = app.login_as_admin().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.admin_username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+/// Check that a password hashed with outdated parameters is re-hashed on login.
+#[tokio::test]
+async fn outdated_password_hash_is_upgraded_on_login() {
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.admin_password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE username = $2",
+        outdated_hash,
+        app.admin_username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.login_as_admin().await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let stored = sqlx::query!(
+        "SELECT password_hash FROM users WHERE username = $1",
+        app.admin_username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(stored
+        .password_hash
+        .starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+}
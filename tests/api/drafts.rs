@@ -0,0 +1,250 @@
+//! Contains tests for `/admin/drafts` and `/admin/email` endpoints.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Create a draft titled `title` as the logged in admin, and return its id.
+async fn create_draft(app: &TestApp, title: &str) -> Uuid {
+    let response = app
+        .api_client
+        .post(format!("{}/admin/drafts", &app.address))
+        .form(&serde_json::json!({ "title": title }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 303);
+    response.headers()["Location"]
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/drafts/")
+        .parse()
+        .unwrap()
+}
+
+/// Post the draft content to `/admin/drafts/{draft_id}{action}`, e.g. `/autosave`.
+async fn post_draft(
+    app: &TestApp,
+    draft_id: Uuid,
+    action: &str,
+    title: &str,
+    text_content: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!(
+            "{}/admin/drafts/{}{}",
+            &app.address, draft_id, action
+        ))
+        .form(&serde_json::json!({
+            "title": title,
+            "text_content": text_content,
+            "html_content": format!("<p>{}</p>", text_content),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Get `path` as the logged in admin, and return the page.
+async fn get_html(app: &TestApp, path: &str) -> String {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+/// Return `(revision, title, autosaved)` of each revision of the draft, in order.
+async fn revisions(app: &TestApp, draft_id: Uuid) -> Vec<(i32, String, bool)> {
+    sqlx::query!(
+        r#"
+        SELECT revision, title, autosaved
+        FROM newsletter_draft_revisions
+        WHERE draft_id = $1
+        ORDER BY revision
+        "#,
+        draft_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.revision, r.title, r.autosaved))
+    .collect()
+}
+
+/// Check that every save with new content stores a new revision, shown on the draft page.
+#[tokio::test]
+async fn saves_store_new_revisions() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let draft_id = create_draft(&app, "First title").await;
+
+    let response = post_draft(&app, draft_id, "", "Second title", "Hello").await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    post_draft(&app, draft_id, "", "Second title", "Hello").await;
+    post_draft(&app, draft_id, "", "Third title", "Hello").await;
+
+    assert_eq!(
+        revisions(&app, draft_id).await,
+        vec![
+            (1, "First title".into(), false),
+            (2, "Second title".into(), false),
+            (3, "Third title".into(), false),
+        ]
+    );
+    let html_page = get_html(&app, &format!("/admin/drafts/{}", draft_id)).await;
+    assert!(html_page.contains("<p>Revision 3</p>"));
+    assert!(html_page.contains(r#"name="text_content" rows="20" cols="50">Hello</textarea>"#));
+    let html_page = get_html(&app, "/admin/drafts").await;
+    assert!(html_page.contains("Third title</a> (revision 3"));
+}
+
+/// Check that autosaves replace each other until the draft is saved.
+#[tokio::test]
+async fn autosaves_replace_each_other_until_a_save() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let draft_id = create_draft(&app, "Title").await;
+
+    let response = post_draft(&app, draft_id, "/autosave", "Title", "Hel").await;
+    assert_eq!(response.status().as_u16(), 204);
+    post_draft(&app, draft_id, "/autosave", "Title", "Hello").await;
+    assert_eq!(
+        revisions(&app, draft_id).await,
+        vec![(1, "Title".into(), false), (2, "Title".into(), true)]
+    );
+
+    // Saving the autosaved content keeps it for good: the next autosave is a new revision.
+    post_draft(&app, draft_id, "", "Title", "Hello").await;
+    post_draft(&app, draft_id, "/autosave", "Title", "Hello there").await;
+    assert_eq!(
+        revisions(&app, draft_id).await,
+        vec![
+            (1, "Title".into(), false),
+            (2, "Title".into(), false),
+            (3, "Title".into(), true),
+        ]
+    );
+    let response = post_draft(&app, Uuid::new_v4(), "/autosave", "Title", "Hello").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+/// Check that the diff shows the lines changed between two revisions.
+#[tokio::test]
+async fn diffs_show_the_changed_lines() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let draft_id = create_draft(&app, "Title").await;
+    post_draft(&app, draft_id, "", "Title", "Hello\nworld").await;
+    post_draft(&app, draft_id, "", "Title", "Hello\nthere").await;
+
+    let html_page = get_html(&app, &format!("/admin/drafts/{}/diff", draft_id)).await;
+    assert!(html_page.contains("Changes from revision 2 to revision 3"));
+    assert!(html_page.contains("  Hello\n<del>- world</del>\n<ins>+ there</ins>"));
+
+    let html_page = get_html(
+        &app,
+        &format!("/admin/drafts/{}/diff?from=1&to=3", draft_id),
+    )
+    .await;
+    assert!(html_page.contains("<ins>+ Hello</ins>\n<ins>+ there</ins>"));
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/drafts/{}/diff?from=7",
+            &app.address, draft_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+/// Check that test sends only go to the logged in admin, once they gave their address.
+#[tokio::test]
+async fn test_sends_go_to_the_logged_in_admin_only() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=hazadus&email=hazadus7%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.login_as_admin().await;
+    let draft_id = create_draft(&app, "Draft title").await;
+    post_draft(&app, draft_id, "", "Draft title", "Hello").await;
+    let send_test = || async {
+        app.api_client
+            .post(format!("{}/admin/drafts/{}/test", &app.address, draft_id))
+            .send()
+            .await
+            .unwrap()
+    };
+    let test_sends = || {
+        app.outbox
+            .messages()
+            .into_iter()
+            .filter(|m| m.subject == "[Test] Draft title")
+            .collect::<Vec<_>>()
+    };
+
+    let response = send_test().await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+    assert!(test_sends().is_empty());
+    let html_page = get_html(&app, &format!("/admin/drafts/{}", draft_id)).await;
+    assert!(html_page.contains("Add your email address"));
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/email", &app.address))
+        .form(&serde_json::json!({"email": "not-an-email"}))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/email");
+    assert!(get_html(&app, "/admin/email")
+        .await
+        .contains("is not a valid email address"));
+    app.api_client
+        .post(format!("{}/admin/email", &app.address))
+        .form(&serde_json::json!({"email": "admin@example.com"}))
+        .send()
+        .await
+        .unwrap();
+    send_test().await;
+
+    let sent = test_sends();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "admin@example.com");
+    assert_eq!(sent[0].html_body, "<p>Hello</p>");
+    assert!(sent[0].headers.is_empty());
+    assert_eq!(app.wait_for_messages(2).await.len(), 2);
+    let html_page = get_html(&app, &format!("/admin/drafts/{}", draft_id)).await;
+    assert!(html_page.contains("A test issue has been sent to admin@example.com."));
+}
+
+/// Check that the publish form can be filled with a draft.
+#[tokio::test]
+async fn the_publish_form_is_filled_with_a_draft() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let draft_id = create_draft(&app, "Title").await;
+    post_draft(&app, draft_id, "", "Title", "Hello").await;
+
+    let html_page = get_html(&app, &format!("/admin/newsletters?draft_id={}", draft_id)).await;
+
+    assert!(html_page.contains(r#"rows="20" cols="50">Hello</textarea>"#));
+    assert!(html_page.contains(r#"rows="20" cols="50">&lt;p&gt;Hello&lt;/p&gt;</textarea>"#));
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters?draft_id={}",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,8 +1,10 @@
 //! Shared helper code for test suite.
-use newsletter::configuration::get_configuration;
+use newsletter::configuration::{get_configuration, EmailTransportKind, Settings};
+use newsletter::email_client::{EmailClient, EmailMessage, InMemoryTransport};
 use newsletter::startup::{get_connection_pool, Application};
 use newsletter::telemetry::{get_subscriber, init_subscriber};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -22,14 +24,59 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub db_pool: PgPool,
+    pub outbox: InMemoryTransport,
+    pub admin_username: String,
+    pub admin_password: String,
+    /// Keeps cookies between requests, and doesn't follow redirects.
+    pub api_client: reqwest::Client,
+}
+
+/// Confirmation links embedded in the confirmation email.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
 }
 
 /// Launch the application in background.
 /// Bind TCP listener to random port.
 /// Create new database with random name to isolate test runs.
-/// Return `TestApp` including server address and database connection pool.
+/// Keep sent emails in an in-memory outbox instead of delivering them.
+/// Return `TestApp` including server address, database connection pool and the outbox.
 pub async fn spawn_app() -> TestApp {
+    let outbox = InMemoryTransport::new();
+    let transport = outbox.clone();
+    spawn_app_with(outbox, move |configuration| {
+        EmailClient::new(
+            configuration
+                .email_client
+                .sender()
+                .expect("Invalid sender email address."),
+            transport,
+            configuration.email_client.retry.policy(),
+        )
+    })
+    .await
+}
+
+/// Same as `spawn_app`, but send emails through Postmark's API at `postmark_url`, e.g. that of
+/// a `MockServer`. The outbox stays empty.
+pub async fn spawn_app_with_postmark(postmark_url: String) -> TestApp {
+    spawn_app_with(InMemoryTransport::new(), |configuration| {
+        let mut settings = configuration.email_client.clone();
+        settings.kind = EmailTransportKind::Postmark;
+        settings.base_url = postmark_url;
+        settings.client()
+    })
+    .await
+}
+
+/// Launch the application, sending emails with the client built by `email_client`.
+async fn spawn_app_with(
+    outbox: InMemoryTransport,
+    email_client: impl FnOnce(&Settings) -> EmailClient,
+) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -41,19 +88,36 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        // Don't wait long between retries of failed email requests
+        c.email_client.retry.base_delay_milliseconds = 10;
+        // Make the delivery worker pick up new tasks quickly
+        c.delivery_worker.poll_interval_milliseconds = 50;
         c
     };
 
-    let application = Application::build(configuration.clone())
+    let email_client = email_client(&configuration);
+    let application = Application::build_with_email_client(configuration.clone(), email_client)
         .await
         .expect("Failed to build application.");
     // Get the port before spawning the application
-    let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
+    let application_port = application.port();
+    let address = format!("http://127.0.0.1:{}", application_port);
+    tokio::spawn(application.run_until_stopped());
+
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
 
     TestApp {
         address,
+        port: application_port,
         db_pool: get_connection_pool(&configuration.database),
+        outbox,
+        admin_username: configuration.admin.username.clone(),
+        admin_password: configuration.admin.password.expose_secret().clone(),
+        api_client,
     }
 }
 
@@ -61,11 +125,310 @@ impl TestApp {
     /// Post `body` as `x-www-form-urlencoded` to the `/subscriptions` endpoint using `reqwest`.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
+
+    /// Post `body` as JSON to the `/newsletters` endpoint, authenticated as admin,
+    /// with a random idempotency key.
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_idempotency_key(body, &Uuid::new_v4().to_string())
+            .await
+    }
+
+    /// Post `body` as JSON to the `/newsletters` endpoint, authenticated as admin,
+    /// with the given idempotency key.
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Post `body` as `x-www-form-urlencoded` to the `/login` endpoint.
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Log in with the admin credentials.
+    pub async fn login_as_admin(&self) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &self.admin_username,
+            "password": &self.admin_password,
+        }))
+        .await
+    }
+
+    /// Get the login form.
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Get the admin dashboard.
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Get the admin dashboard HTML.
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    /// Post to the `/admin/logout` endpoint.
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter().await.text().await.unwrap()
+    }
+
+    pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api_keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_api_keys<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_keys", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create an API key with space separated `scopes` as the logged in admin, and return it.
+    pub async fn create_api_key(&self, scopes: &str) -> String {
+        let html_page = self
+            .post_api_keys(&serde_json::json!({
+                "name": "Test client",
+                "scopes": scopes,
+            }))
+            .await
+            .text()
+            .await
+            .unwrap();
+        extract_code(&html_page)
+    }
+
+    pub async fn post_users<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Invite an admin user with `role` as the logged in owner, and return their temporary password.
+    pub async fn invite_user(&self, username: &str, role: &str) -> String {
+        let html_page = self
+            .post_users(&serde_json::json!({
+                "username": username,
+                "role": role,
+            }))
+            .await
+            .text()
+            .await
+            .unwrap();
+        extract_code(&html_page)
+    }
+
+    pub async fn post_api_subscribers(
+        &self,
+        api_key: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/subscribers", &self.address))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Wait until the background worker has processed all tasks in the delivery queue.
+    pub async fn wait_until_delivery_queue_is_empty(&self) {
+        for _ in 0..100 {
+            let pending = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+                .fetch_one(&self.db_pool)
+                .await
+                .expect("Failed to count pending deliveries.");
+            if pending.count == Some(0) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("The delivery queue was not emptied in time.");
+    }
+
+    /// Wait until the outbox holds at least `n` messages, and return them.
+    // Confirmation and data request emails are sent in the background, after the response.
+    pub async fn wait_for_messages(&self, n: usize) -> Vec<EmailMessage> {
+        for _ in 0..100 {
+            let messages = self.outbox.messages();
+            if messages.len() >= n {
+                return messages;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("{} emails were not sent in time.", n);
+    }
+
+    /// Extract the confirmation links embedded in the email.
+    /// The preferences link of welcome emails is ignored.
+    pub fn get_confirmation_links(&self, email: &EmailMessage) -> ConfirmationLinks {
+        // Extract the link from one of the email bodies.
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| !l.as_str().contains("/preferences?"))
+                .collect();
+            assert_eq!(links.len(), 1);
+            let raw_link = links[0].as_str().to_owned();
+            let mut confirmation_link = reqwest::Url::parse(&raw_link).unwrap();
+            // Let's make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            // Rewrite the port to point to the test instance of the app
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+
+        let html = get_link(&email.html_body);
+        let plain_text = get_link(&email.text_body);
+        ConfirmationLinks { html, plain_text }
+    }
+
+    /// Extract the preferences link from the plain text body of the welcome email.
+    pub fn get_preferences_link(&self, email: &EmailMessage) -> reqwest::Url {
+        let raw_link = linkify::LinkFinder::new()
+            .links(&email.text_body)
+            .map(|l| l.as_str().to_owned())
+            .find(|l| l.contains("/preferences?"))
+            .expect("No preferences link in the email.");
+        let mut preferences_link = reqwest::Url::parse(&raw_link).unwrap();
+        assert_eq!(preferences_link.host_str().unwrap(), "127.0.0.1");
+        preferences_link.set_port(Some(self.port)).unwrap();
+        preferences_link
+    }
+
+    /// Extract the one-click unsubscribe link from the `List-Unsubscribe` header of the email.
+    pub fn get_unsubscribe_link(&self, email: &EmailMessage) -> reqwest::Url {
+        let (_, value) = email
+            .headers
+            .iter()
+            .find(|(name, _)| name == "List-Unsubscribe")
+            .expect("No `List-Unsubscribe` header in the email.");
+        let raw_link = value.trim_start_matches('<').trim_end_matches('>');
+        let mut unsubscribe_link = reqwest::Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+}
+
+/// Check that `response` is a `303 SEE OTHER` redirect to `location`.
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// Return the content of the first `<code>` element of `html_page`: secrets shown once.
+pub fn extract_code(html_page: &str) -> String {
+    let start = html_page.find("<code>").expect("No <code> in the page.") + "<code>".len();
+    let end = html_page[start..].find("</code>").unwrap() + start;
+    html_page[start..end].to_string()
 }
@@ -0,0 +1,88 @@
+//! Contains tests for `/login` endpoint.
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+/// Check that the login form is served.
+#[tokio::test]
+async fn login_form_is_served() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"<form action="/login" method="post">"#));
+}
+
+/// Check that invalid credentials redirect back to the login form,
+/// which shows an error message once.
+#[tokio::test]
+async fn an_error_message_is_shown_on_failure() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (uuid::Uuid::new_v4().to_string(), app.admin_password.clone()),
+        (app.admin_username.clone(), uuid::Uuid::new_v4().to_string()),
+    ];
+
+    for (username, password) in test_cases {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": username,
+                "password": password,
+            }))
+            .await;
+
+        assert_is_redirect_to(&response, "/login");
+        let html_page = app.get_login_html().await;
+        assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+        let html_page = app.get_login_html().await;
+        assert!(!html_page.contains("Authentication failed."));
+    }
+}
+
+/// Check that a forged flash messages cookie is ignored.
+#[tokio::test]
+async fn forged_flash_messages_are_ignored() {
+    let app = spawn_app().await;
+    let forged = serde_json::json!([{"level": "error", "content": "Forged-message."}]);
+
+    let html_page = reqwest::Client::new()
+        .get(format!("{}/login", &app.address))
+        .header("Cookie", format!("_flash={}", forged))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(!html_page.contains("Forged-message."));
+}
+
+/// Check that the user is redirected to the admin dashboard after logging in.
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    let app = spawn_app().await;
+
+    let response = app.login_as_admin().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}!", app.admin_username)));
+}
+
+/// Check that every login gives the session a new id, preventing session fixation.
+#[tokio::test]
+async fn session_id_is_rotated_on_login() {
+    let app = spawn_app().await;
+    let session_cookie = |response: &reqwest::Response| {
+        response
+            .cookies()
+            .find(|c| c.name() == "id")
+            .expect("No session cookie was set.")
+            .value()
+            .to_owned()
+    };
+
+    let first_session = session_cookie(&app.login_as_admin().await);
+    let second_session = session_cookie(&app.login_as_admin().await);
+
+    assert_ne!(first_session, second_session);
+}
@@ -1,4 +1,19 @@
 //! Test suite for API.
+mod admin_dashboard;
+mod admin_subscribers;
+mod admin_users;
+mod api_keys;
+mod change_password;
+mod drafts;
 mod health_check;
 mod helpers;
+mod login;
+mod newsletters;
+mod preferences;
+mod scheduled_newsletters;
+mod subscriber_export;
+mod subscriber_import;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_unsubscribe;
@@ -0,0 +1,538 @@
+//! Contains tests for `/newsletters` and `/admin/newsletters` endpoints.
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with_postmark, ConfirmationLinks, TestApp,
+};
+use newsletter::email_client::EmailMessage;
+use uuid::Uuid;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Use the public API of the application under test to create an unconfirmed subscriber.
+async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    sign_up(app, "name=hazadus&email=hazadus7%40gmail.com").await
+}
+
+/// Sign up with the form `body`, and return the confirmation links.
+async fn sign_up(app: &TestApp, body: &str) -> ConfirmationLinks {
+    let n_sent = app.outbox.messages().len();
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = app.wait_for_messages(n_sent + 1).await.pop().unwrap();
+    app.get_confirmation_links(&email)
+}
+
+/// Use the public API of the application under test to create a confirmed subscriber.
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Return emails with the newsletter issue sent so far.
+fn sent_issues(app: &TestApp) -> Vec<EmailMessage> {
+    app.outbox
+        .messages()
+        .into_iter()
+        .filter(|m| m.subject == "Newsletter title")
+        .collect()
+}
+
+/// Return valid newsletter issue JSON body.
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+/// Check that unconfirmed subscribers don't get the issue.
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_until_delivery_queue_is_empty().await;
+    assert!(sent_issues(&app).is_empty());
+}
+
+/// Check that confirmed subscribers get the issue.
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+    app.wait_until_delivery_queue_is_empty().await;
+    let sent = sent_issues(&app);
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "hazadus7@gmail.com");
+}
+
+/// Check that confirmed subscribers with invalid stored email are skipped and reported.
+#[tokio::test]
+async fn subscribers_with_invalid_stored_emails_are_skipped_and_reported() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES ($1, 'definitely-not-an-email', 'broken', now(), 'confirmed', 'some-token')
+            RETURNING id
+        )
+        INSERT INTO subscription_lists (subscriber_id, list_id)
+        SELECT inserted.id, lists.list_id FROM inserted CROSS JOIN lists
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+    assert_eq!(report["skipped"][0]["email"], "definitely-not-an-email");
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Check that a failed delivery is kept in the queue and retried later.
+#[tokio::test]
+async fn failed_deliveries_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.outbox.fail_next_attempts(1);
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Check that a delivery rejected for good is dropped from the queue without being retried.
+#[tokio::test]
+async fn rejected_deliveries_are_not_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.outbox.reject_next_attempts(1);
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_until_delivery_queue_is_empty().await;
+    // A retry would have been delivered: only the first attempt is rejected.
+    assert!(sent_issues(&app).is_empty());
+}
+
+/// Check that deliveries are postponed, not dropped, when the whole batch request is rejected,
+/// e.g. because of a bad server token.
+#[tokio::test]
+async fn deliveries_of_a_rejected_batch_are_retried() {
+    let postmark = MockServer::start().await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&postmark)
+        .await;
+    let app = spawn_app_with_postmark(postmark.uri()).await;
+    sqlx::query!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES ($1, 'hazadus7@gmail.com', 'hazadus', now(), 'confirmed', 'token')
+            RETURNING id
+        )
+        INSERT INTO subscription_lists (subscriber_id, list_id)
+        SELECT inserted.id, lists.list_id FROM inserted CROSS JOIN lists
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    for _ in 0..100 {
+        let task = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap()
+            .expect("The delivery was dropped.");
+        if task.n_retries > 0 {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The delivery was not attempted in time.");
+}
+
+/// Check that only the failed deliveries of a batch are retried.
+#[tokio::test]
+async fn only_failed_deliveries_of_a_batch_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES ($1, 'another@example.com', 'another', now(), 'confirmed', 'another-token')
+            RETURNING id
+        )
+        INSERT INTO subscription_lists (subscriber_id, list_id)
+        SELECT inserted.id, lists.list_id FROM inserted CROSS JOIN lists
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // Fail the first message of the batch
+    app.outbox.fail_next_attempts(1);
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_until_delivery_queue_is_empty().await;
+    let mut recipients: Vec<_> = sent_issues(&app).into_iter().map(|m| m.to).collect();
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        vec!["another@example.com", "hazadus7@gmail.com"]
+    );
+}
+
+/// Check that invalid issue JSON bodies are rejected with `400 BAD REQUEST`.
+#[tokio::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_newsletters(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+/// Check that requests without `Authorization` header are rejected with `401 UNAUTHORIZED`.
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+/// Check that requests with invalid credentials are rejected with `401 UNAUTHORIZED`.
+#[tokio::test]
+async fn requests_with_invalid_credentials_are_rejected() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (Uuid::new_v4().to_string(), app.admin_password.clone()),
+        (app.admin_username.clone(), Uuid::new_v4().to_string()),
+    ];
+
+    for (username, password) in test_cases {
+        let response = reqwest::Client::new()
+            .post(format!("{}/newsletters", &app.address))
+            .basic_auth(username, Some(password))
+            .json(&newsletter_request_body())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(401, response.status().as_u16());
+    }
+}
+
+/// Check that requests without `Idempotency-Key` header are rejected with `400 BAD REQUEST`.
+#[tokio::test]
+async fn requests_missing_idempotency_key_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+/// Check that retrying the request with the same idempotency key doesn't send the issue twice,
+/// and returns the same response.
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let first_response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(first_response.status().as_u16(), 200);
+    let first_report = first_response.text().await.unwrap();
+
+    let second_response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(second_response.status().as_u16(), 200);
+    assert_eq!(
+        second_response.headers()["Content-Type"],
+        "application/json"
+    );
+    assert_eq!(first_report, second_response.text().await.unwrap());
+
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Check that two concurrent requests with the same idempotency key send the issue once,
+/// and get the same response.
+#[tokio::test]
+async fn concurrent_requests_are_handled_gracefully() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response1 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key);
+    let response2 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Return valid body of the admin form publishing an issue.
+fn newsletter_form_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "lists": "weekly-digest",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+/// Check that anonymous users can't see the publish form, nor publish with it.
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_from_the_form() {
+    let app = spawn_app().await;
+
+    let form = app.get_publish_newsletter().await;
+    let publish = app.post_publish_newsletter(&newsletter_form_body()).await;
+
+    assert_is_redirect_to(&form, "/login");
+    assert_is_redirect_to(&publish, "/login");
+}
+
+/// Check that publishing from the form queues the issue, and shows a message once.
+#[tokio::test]
+async fn issues_published_from_the_form_are_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_admin().await;
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(r#"<form action="/admin/newsletters" method="post">"#));
+
+    let response = app.post_publish_newsletter(&newsletter_form_body()).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been queued for delivery.</i></p>"));
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(!html_page.contains("queued for delivery"));
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Check that submitting the same form twice delivers the issue once, and shows the message again.
+#[tokio::test]
+async fn publishing_from_the_form_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_admin().await;
+    let body = newsletter_form_body();
+
+    for _ in 0..2 {
+        let response = app.post_publish_newsletter(&body).await;
+
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = app.get_publish_newsletter_html().await;
+        assert!(html_page.contains("The newsletter issue has been queued for delivery."));
+    }
+    app.wait_until_delivery_queue_is_empty().await;
+    assert_eq!(sent_issues(&app).len(), 1);
+}
+
+/// Check that issues published to lists only reach their subscribers.
+#[tokio::test]
+async fn issues_are_delivered_to_subscribers_of_their_lists() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let events_only = sign_up(&app, "name=events&email=events%40example.com&lists=events").await;
+    reqwest::get(events_only.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let mut body = newsletter_request_body();
+    body["lists"] = serde_json::json!(["release-notes"]);
+
+    let response = app.post_newsletters(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+    app.wait_until_delivery_queue_is_empty().await;
+    let sent = sent_issues(&app);
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "hazadus7@gmail.com");
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug AS "slug!"
+        FROM newsletter_issue_lists il
+        JOIN lists l ON l.list_id = il.list_id
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].slug, "release-notes");
+}
+
+/// Check that issues published to an unknown list are rejected, and not stored.
+#[tokio::test]
+async fn issues_published_to_unknown_lists_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut body = newsletter_request_body();
+    body["lists"] = serde_json::json!(["events", "gossip"]);
+
+    let response = app.post_newsletters(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "lists");
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+/// Check that deliveries to weekly subscribers wait for next Monday.
+#[tokio::test]
+async fn weekly_subscribers_get_issues_on_monday() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET delivery_frequency = 'weekly'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let task = sqlx::query!(
+        r#"
+        SELECT
+            execute_after > now() AS "is_later!",
+            execute_after <= now() + interval '1 week' AS "is_within_a_week!",
+            EXTRACT(ISODOW FROM execute_after AT TIME ZONE 'UTC')::int AS "day_of_week!",
+            (execute_after AT TIME ZONE 'UTC')::time AS "time!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(task.is_later);
+    assert!(task.is_within_a_week);
+    assert_eq!(task.day_of_week, 1);
+    assert_eq!(task.time, chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+    assert!(sent_issues(&app).is_empty());
+}
+
+/// Check that the form refuses to publish an issue to no list.
+#[tokio::test]
+async fn issues_published_from_the_form_need_a_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_admin().await;
+    let mut body = newsletter_form_body();
+    body.as_object_mut().unwrap().remove("lists");
+
+    let response = app.post_publish_newsletter(&body).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Choose at least one list to publish the issue to."));
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
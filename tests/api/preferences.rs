@@ -0,0 +1,267 @@
+//! Contains tests for `/preferences` endpoints.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Sign up `hazadus` with `body`, and return the preferences link from the welcome email.
+async fn sign_up(app: &TestApp, body: &str) -> reqwest::Url {
+    let n_sent = app.outbox.messages().len();
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = app.wait_for_messages(n_sent + 1).await.pop().unwrap();
+    app.get_preferences_link(&email)
+}
+
+/// Return slugs of the lists `hazadus` is subscribed to, in alphabetical order.
+async fn subscribed_lists(app: &TestApp) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM subscription_lists sl
+        JOIN lists l ON l.list_id = sl.list_id
+        JOIN subscriptions s ON s.id = sl.subscriber_id
+        WHERE s.email = 'hazadus7@gmail.com'
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.slug)
+    .collect()
+}
+
+/// Return the time zone of `hazadus`.
+async fn time_zone(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT time_zone FROM subscriptions WHERE email = 'hazadus7@gmail.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .time_zone
+}
+
+/// Post `body` to the preferences form, without following the redirect.
+async fn post_preferences(app: &TestApp, body: String) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/preferences", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Return the `unsubscribe_token` query parameter of `link`.
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(name, _)| name == "unsubscribe_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+/// Check that signups without lists get every list, and signups with lists only get those.
+#[tokio::test]
+async fn signups_get_the_chosen_lists_or_all_of_them() {
+    let app = spawn_app().await;
+
+    sign_up(&app, "name=hazadus&email=hazadus7%40gmail.com").await;
+    assert_eq!(
+        subscribed_lists(&app).await,
+        vec!["events", "release-notes", "weekly-digest"]
+    );
+
+    // Signing up again while pending replaces the choice.
+    sign_up(
+        &app,
+        "name=hazadus&email=hazadus7%40gmail.com&lists=events&lists=release-notes",
+    )
+    .await;
+    assert_eq!(
+        subscribed_lists(&app).await,
+        vec!["events", "release-notes"]
+    );
+}
+
+/// Check that signups with an unknown list are rejected with problem details naming `lists`.
+#[tokio::test]
+async fn signups_with_unknown_lists_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=hazadus&email=hazadus7%40gmail.com&lists=gossip".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "lists");
+    assert!(app.outbox.messages().is_empty());
+}
+
+/// Check that the preferences page shows the lists and frequency of the subscriber.
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_choice() {
+    let app = spawn_app().await;
+    let link = sign_up(&app, "name=hazadus&email=hazadus7%40gmail.com&lists=events").await;
+
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"value="events" checked>"#));
+    assert!(html_page.contains(r#"value="immediately" checked>"#));
+    assert_eq!(html_page.matches(" checked>").count(), 2);
+}
+
+/// Check that saving the form stores the lists and frequency, and shows a message once.
+#[tokio::test]
+async fn saved_preferences_are_stored() {
+    let app = spawn_app().await;
+    let link = sign_up(&app, "name=hazadus&email=hazadus7%40gmail.com").await;
+
+    let response = post_preferences(
+        &app,
+        format!(
+            "unsubscribe_token={}&lists=weekly-digest&lists=events&frequency=weekly",
+            token(&link)
+        ),
+    )
+    .await;
+
+    assert_is_redirect_to(
+        &response,
+        &format!("/preferences?unsubscribe_token={}", token(&link)),
+    );
+    assert_eq!(
+        subscribed_lists(&app).await,
+        vec!["events", "weekly-digest"]
+    );
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="weekly" checked>"#));
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("have been saved"));
+
+    // Unchecking every list is allowed.
+    post_preferences(
+        &app,
+        format!("unsubscribe_token={}&frequency=weekly", token(&link)),
+    )
+    .await;
+    assert!(subscribed_lists(&app).await.is_empty());
+}
+
+/// Check that the time zone is shown and stored, and can be cleared.
+#[tokio::test]
+async fn the_time_zone_is_stored() {
+    let app = spawn_app().await;
+    let link = sign_up(
+        &app,
+        "name=hazadus&email=hazadus7%40gmail.com&time_zone=Asia%2FTokyo",
+    )
+    .await;
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"name="time_zone" value="Asia&#x2F;Tokyo""#));
+
+    post_preferences(
+        &app,
+        format!(
+            "unsubscribe_token={}&frequency=weekly&time_zone=Europe%2FBerlin",
+            token(&link)
+        ),
+    )
+    .await;
+    assert_eq!(time_zone(&app).await.as_deref(), Some("Europe/Berlin"));
+
+    post_preferences(
+        &app,
+        format!(
+            "unsubscribe_token={}&frequency=weekly&time_zone=",
+            token(&link)
+        ),
+    )
+    .await;
+    assert_eq!(time_zone(&app).await, None);
+}
+
+/// Check that unknown tokens, lists and time zones are rejected.
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    let app = spawn_app().await;
+    let link = sign_up(&app, "name=hazadus&email=hazadus7%40gmail.com").await;
+    let test_cases = vec![
+        (
+            "unsubscribe_token=unknown&lists=events&frequency=weekly".to_owned(),
+            401,
+            "unknown token",
+        ),
+        (
+            format!(
+                "unsubscribe_token={}&lists=gossip&frequency=weekly",
+                token(&link)
+            ),
+            400,
+            "unknown list",
+        ),
+        (
+            format!(
+                "unsubscribe_token={}&lists=events&frequency=hourly",
+                token(&link)
+            ),
+            400,
+            "unknown frequency",
+        ),
+        (
+            format!(
+                "unsubscribe_token={}&lists=events&frequency=weekly&time_zone=Mars%2FOlympus",
+                token(&link)
+            ),
+            400,
+            "unknown time zone",
+        ),
+    ];
+
+    for (body, status, description) in test_cases {
+        let response = post_preferences(&app, body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The preferences weren't rejected with {}.",
+            description
+        );
+    }
+    assert_eq!(subscribed_lists(&app).await.len(), 3);
+    let response = reqwest::get(format!(
+        "{}/preferences?unsubscribe_token=unknown",
+        &app.address
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,324 @@
+//! Contains tests for scheduled publishing with `/newsletters` and `/admin/newsletters` endpoints.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Sign up and confirm `hazadus`.
+async fn create_confirmed_subscriber(app: &TestApp) {
+    sign_up_and_confirm(app, "name=hazadus&email=hazadus7%40gmail.com").await;
+}
+
+/// Sign up with the form `body`, and confirm.
+async fn sign_up_and_confirm(app: &TestApp, body: &str) {
+    let n_sent = app.outbox.messages().len();
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = app.wait_for_messages(n_sent + 1).await.pop().unwrap();
+    reqwest::get(app.get_confirmation_links(&email).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Return valid newsletter issue JSON body, scheduled for `scheduled_for` in `time_zone`.
+fn scheduled_request_body(scheduled_for: &str, time_zone: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "scheduled_for": scheduled_for,
+        "time_zone": time_zone,
+    })
+}
+
+/// Schedule an issue for 2030-07-01 08:00 UTC, and return its id.
+async fn schedule_issue(app: &TestApp) -> Uuid {
+    let response = app
+        .post_newsletters(scheduled_request_body("2030-07-01T08:00", "UTC"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    report["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+/// Post `body` as JSON to `/newsletters/{newsletter_issue_id}/{action}`, authenticated as admin.
+async fn post_schedule_action(
+    app: &TestApp,
+    newsletter_issue_id: Uuid,
+    action: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/newsletters/{}/{}",
+            &app.address, newsletter_issue_id, action
+        ))
+        .basic_auth(&app.admin_username, Some(&app.admin_password))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Return the status of the issue.
+async fn issue_status(app: &TestApp, newsletter_issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+/// Wait for the delivery worker to publish the issue.
+async fn wait_until_published(app: &TestApp, newsletter_issue_id: Uuid) {
+    for _ in 0..100 {
+        if issue_status(app, newsletter_issue_id).await == "published" {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The scheduled issue was not published in time.");
+}
+
+/// Check that a scheduled issue is only delivered once it is due.
+#[tokio::test]
+async fn scheduled_issues_are_delivered_when_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let newsletter_issue_id = schedule_issue(&app).await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert_eq!(issue_status(&app, newsletter_issue_id).await, "scheduled");
+    assert_eq!(app.wait_for_messages(1).await.len(), 1);
+
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute'
+        WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    wait_until_published(&app, newsletter_issue_id).await;
+    app.wait_until_delivery_queue_is_empty().await;
+
+    let sent = app.outbox.messages().pop().unwrap();
+    assert_eq!(sent.subject, "Newsletter title");
+    assert_eq!(sent.to, "hazadus7@gmail.com");
+}
+
+/// Check that local times are resolved in the time zone, daylight saving time included.
+#[tokio::test]
+async fn scheduled_times_are_resolved_in_their_time_zone() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("2030-07-01T08:00", "2030-07-01T06:00:00Z", "summer time"),
+        ("2030-01-07T08:00", "2030-01-07T07:00:00Z", "standard time"),
+    ];
+
+    for (local_time, expected, description) in test_cases {
+        let response = app
+            .post_newsletters(scheduled_request_body(local_time, "Europe/Berlin"))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        let report: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            report["scheduled_for"], expected,
+            "The time wasn't resolved in {}.",
+            description
+        );
+        assert_eq!(report["time_zone"], "Europe/Berlin");
+    }
+}
+
+/// Check that an issue scheduled in subscriber time zones is published as soon as the local time
+/// is reached somewhere, and queued for that local time in the time zone of each subscriber,
+/// falling back to the time zone of the schedule.
+#[tokio::test]
+async fn issues_in_subscriber_time_zones_are_delivered_at_each_local_time() {
+    let app = spawn_app().await;
+    sign_up_and_confirm(
+        &app,
+        "name=hazadus&email=hazadus7%40gmail.com&time_zone=Asia%2FTokyo",
+    )
+    .await;
+    sign_up_and_confirm(&app, "name=guest&email=guest%40example.com").await;
+
+    // An hour from now in UTC-12, which is long past in Tokyo.
+    let local_time = (chrono::Utc::now() - chrono::Duration::hours(11))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string();
+    let mut body = scheduled_request_body(&local_time, "Etc/GMT+12");
+    body["in_subscriber_time_zones"] = true.into();
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["in_subscriber_time_zones"], true);
+    let newsletter_issue_id = report["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    wait_until_published(&app, newsletter_issue_id).await;
+
+    for _ in 0..100 {
+        if app
+            .outbox
+            .messages()
+            .iter()
+            .any(|m| m.subject == "Newsletter title")
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let sent: Vec<_> = app
+        .outbox
+        .messages()
+        .into_iter()
+        .filter(|m| m.subject == "Newsletter title")
+        .collect();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "hazadus7@gmail.com");
+    let pending = sqlx::query!(
+        r#"
+        SELECT
+            subscriber_email,
+            execute_after > now() + interval '50 minutes' AS "later!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(pending.subscriber_email, "guest@example.com");
+    assert!(pending.later);
+}
+
+/// Check that invalid times, unknown time zones and past times are rejected.
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("next monday", "UTC", "scheduled_for", "invalid time"),
+        (
+            "2030-07-01T08:00",
+            "Mars/Olympus",
+            "time_zone",
+            "unknown time zone",
+        ),
+        ("2020-07-01T08:00", "UTC", "scheduled_for", "past time"),
+    ];
+
+    for (local_time, time_zone, invalid_param, description) in test_cases {
+        let response = app
+            .post_newsletters(scheduled_request_body(local_time, time_zone))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The schedule wasn't rejected with {}.",
+            description
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["invalid-params"][0]["name"], invalid_param);
+    }
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+/// Check that scheduled issues can be rescheduled and cancelled, but only while scheduled.
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled_and_cancelled() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = schedule_issue(&app).await;
+
+    let response = post_schedule_action(
+        &app,
+        newsletter_issue_id,
+        "reschedule",
+        serde_json::json!({"scheduled_for": "2030-07-02T08:00", "time_zone": "Asia/Tokyo"}),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["scheduled_for"], "2030-07-01T23:00:00Z");
+
+    let response =
+        post_schedule_action(&app, newsletter_issue_id, "cancel", serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(issue_status(&app, newsletter_issue_id).await, "cancelled");
+
+    let response =
+        post_schedule_action(&app, newsletter_issue_id, "cancel", serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 409);
+    let response = post_schedule_action(
+        &app,
+        newsletter_issue_id,
+        "reschedule",
+        serde_json::json!({"scheduled_for": "2030-07-02T08:00"}),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 409);
+    let response =
+        post_schedule_action(&app, Uuid::new_v4(), "cancel", serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+/// Check that editors can schedule and cancel issues from the admin form.
+#[tokio::test]
+async fn issues_can_be_scheduled_and_cancelled_from_the_form() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "lists": "weekly-digest",
+            "scheduled_for": "2030-07-01T08:00",
+            "time_zone": "Europe/Berlin",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been scheduled.</i></p>"));
+    assert!(html_page.contains(r#"value="2030-07-01T08:00""#));
+
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/cancel",
+            &app.address, newsletter_issue_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("No issue is scheduled."));
+}
@@ -0,0 +1,135 @@
+//! Contains tests for exports of subscribers, with `GET /admin/subscribers/export` endpoint
+//! and to files.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use newsletter::subscriber_export::{export_subscribers_to, ExportFormat};
+
+/// Get the export in `format` as the logged in user.
+async fn get_export(app: &TestApp, format: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!(
+            "{}/admin/subscribers/export?format={}",
+            &app.address, format
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Sign up `hazadus` and confirm them, and sign up `pending` without confirming.
+async fn create_subscribers(app: &TestApp) {
+    app.post_subscriptions("name=hazadus&email=hazadus7%40gmail.com".into())
+        .await;
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions("name=Doe%2C%20John&email=pending%40example.com".into())
+        .await;
+}
+
+/// Check that anonymous users are redirected to the login form.
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = get_export(&app, "csv").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Check that the CSV export has a header row, then a row per subscriber in signup order.
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    create_subscribers(&app).await;
+
+    let response = get_export(&app, "csv").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        r#"attachment; filename="subscribers.csv""#
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "id,email,name,status,subscribed_at,confirmed_at");
+    assert!(lines[1].contains(",hazadus7@gmail.com,hazadus,confirmed,"));
+    assert!(!lines[1].ends_with(','));
+    assert!(lines[2].contains(r#",pending@example.com,"Doe, John",pending_confirmation,"#));
+    assert!(lines[2].ends_with(','));
+}
+
+/// Check that the NDJSON export has a JSON object per subscriber.
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    create_subscribers(&app).await;
+
+    let response = get_export(&app, "ndjson").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 2);
+    assert_eq!(subscribers[0]["email"], "hazadus7@gmail.com");
+    assert_eq!(subscribers[0]["status"], "confirmed");
+    assert!(subscribers[0]["confirmed_at"].is_string());
+    assert_eq!(subscribers[1]["name"], "Doe, John");
+    assert!(subscribers[1]["confirmed_at"].is_null());
+}
+
+/// Check that unknown formats are rejected.
+#[tokio::test]
+async fn unknown_formats_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let response = get_export(&app, "xlsx").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+/// Check that exports larger than a chunk are complete, also when written to a file.
+#[tokio::test]
+async fn large_exports_are_complete() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        SELECT gen_random_uuid(), 'user' || i || '@example.com', 'User ' || i,
+            now() + i * interval '1 second', 'confirmed', md5(i::text)
+        FROM generate_series(1, 3000) AS i
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_export(&app, "ndjson").await;
+    let mut file = vec![];
+    export_subscribers_to(app.db_pool.clone(), ExportFormat::Csv, &mut file)
+        .await
+        .unwrap();
+
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 3000);
+    assert!(lines[2999].contains("user3000@example.com"));
+    let file = String::from_utf8(file).unwrap();
+    assert_eq!(file.lines().count(), 3001);
+}
@@ -0,0 +1,199 @@
+//! Contains tests for CSV imports of subscribers, with `POST /admin/subscribers/import`
+//! endpoint and from files.
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use newsletter::subscriber_import::import_subscribers_from_file;
+use uuid::Uuid;
+
+/// Post `csv` to the import endpoint as the logged in user.
+async fn post_import(app: &TestApp, csv: &str, dry_run: bool) -> reqwest::Response {
+    app.api_client
+        .post(format!(
+            "{}/admin/subscribers/import?dry_run={}",
+            &app.address, dry_run
+        ))
+        .header("Content-Type", "text/csv")
+        .body(csv.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Return emails and statuses of stored subscribers, ordered by email.
+async fn stored_subscribers(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+/// CSV file with a valid row, an invalid one, and a duplicate of the first one.
+const MIXED_CSV: &str = "\
+id,Name,Email
+1,Ann,ann@example.com
+2,Bob,not-an-email
+3,Ann again,ann@example.com
+4,Carol,carol@example.com
+";
+
+/// Check that anonymous users are redirected to the login form, and viewers are forbidden.
+#[tokio::test]
+async fn only_editors_and_owners_can_import() {
+    let app = spawn_app().await;
+    let response = post_import(&app, MIXED_CSV, false).await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.login_as_admin().await;
+    let password = app.invite_user("viewer", "viewer").await;
+    app.post_logout().await;
+    app.post_login(&serde_json::json!({"username": "viewer", "password": password}))
+        .await;
+    let response = post_import(&app, MIXED_CSV, false).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+/// Check that valid rows are stored as confirmed, and the others are reported.
+#[tokio::test]
+async fn import_stores_valid_rows_and_reports_the_others() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    app.post_subscriptions("name=Carol&email=carol%40example.com".into())
+        .await;
+
+    let response = post_import(&app, MIXED_CSV, false).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        report,
+        serde_json::json!({
+            "dry_run": false,
+            "imported": 1,
+            "rejected": [{"row": 3, "reason": "email is not a valid email address"}],
+            "duplicates": [
+                {"row": 4, "email": "ann@example.com"},
+                {"row": 5, "email": "carol@example.com"},
+            ],
+        })
+    );
+    // The existing subscriber is left as it was.
+    assert_eq!(
+        stored_subscribers(&app).await,
+        vec![
+            ("ann@example.com".into(), "confirmed".into()),
+            ("carol@example.com".into(), "pending_confirmation".into()),
+        ]
+    );
+    // Imported subscribers get every list.
+    let lists = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscription_lists sl
+        JOIN subscriptions s ON s.id = sl.subscriber_id
+        WHERE s.email = 'ann@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(lists.count, 3);
+}
+
+/// Check that a dry run reports the same as an import, but doesn't store anything.
+#[tokio::test]
+async fn dry_run_stores_nothing() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+
+    let response = post_import(&app, MIXED_CSV, true).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["rejected"].as_array().unwrap().len(), 1);
+    assert_eq!(report["duplicates"].as_array().unwrap().len(), 1);
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+/// Check that files without `email` and `name` columns are rejected as a whole.
+#[tokio::test]
+async fn files_without_required_columns_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let test_cases = vec![
+        ("", "empty file"),
+        ("email\nann@example.com\n", "missing name column"),
+        ("ann@example.com,Ann\n", "missing header row"),
+    ];
+
+    for (csv, description) in test_cases {
+        let response = post_import(&app, csv, false).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The import didn't fail with {}.",
+            description
+        );
+    }
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+/// Check that rows over the maximum record size are rejected without stopping the import.
+#[tokio::test]
+async fn rows_over_the_maximum_size_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_admin().await;
+    let csv = format!(
+        "email,name\nann@example.com,{}\ncarol@example.com,Carol\n",
+        "n".repeat(100 * 1024)
+    );
+
+    let response = post_import(&app, &csv, false).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["rejected"][0]["row"], 2);
+    assert!(report["rejected"][0]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("longer than 65536 bytes"));
+    assert_eq!(
+        stored_subscribers(&app).await,
+        vec![("carol@example.com".to_string(), "confirmed".to_string())]
+    );
+}
+
+/// Check that files larger than a batch are imported whole, and importing them again
+/// only reports duplicates.
+#[tokio::test]
+async fn large_files_are_imported_in_batches() {
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..2500 {
+        csv.push_str(&format!("user{}@example.com,User {}\n", i, i));
+    }
+    let path = std::env::temp_dir().join(format!("{}.csv", Uuid::new_v4()));
+    tokio::fs::write(&path, csv).await.unwrap();
+
+    let first = import_subscribers_from_file(&app.db_pool, &path, false)
+        .await
+        .unwrap();
+    let second = import_subscribers_from_file(&app.db_pool, &path, false)
+        .await
+        .unwrap();
+    tokio::fs::remove_file(&path).await.unwrap();
+
+    assert_eq!(first.imported, 2500);
+    assert!(first.duplicates.is_empty());
+    assert_eq!(second.imported, 0);
+    assert_eq!(second.duplicates.len(), 2500);
+    assert_eq!(second.duplicates[0].row, 2);
+    assert_eq!(stored_subscribers(&app).await.len(), 2500);
+}
@@ -12,13 +12,156 @@ async fn subscribe_return_200_for_valid_form_and_data_properly_saved() {
 
     assert_eq!(200, response.status().as_u16());
 
-    let saved = sqlx::query!("SELECT email, name FROM subscriptions",)
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
         .fetch_one(&app.db_pool)
         .await
         .expect("Failed to fetch saved subscription from database.");
 
     assert_eq!(saved.email, "hazadus7@gmail.com");
     assert_eq!(saved.name, "hazadus");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+/// Check that the optional time zone of the signup is stored.
+#[tokio::test]
+async fn subscribe_stores_the_time_zone() {
+    let app = spawn_app().await;
+
+    let body = "name=hazadus&email=hazadus7%40gmail.com&time_zone=Asia%2FTokyo";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT time_zone FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.time_zone.as_deref(), Some("Asia/Tokyo"));
+}
+
+/// Check that a confirmation email is sent to the new subscriber.
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_for_valid_data() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+
+    let sent = app.wait_for_messages(1).await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "hazadus7@gmail.com");
+}
+
+/// Check that the confirmation email contains the same link in both HTML and text bodies.
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+/// Check that `/subscriptions` endpoint returns `200 OK` even if the confirmation email can't
+/// be sent, like for a confirmed email which gets none: the response doesn't reveal which
+/// emails are on the list.
+#[tokio::test]
+async fn subscribe_returns_200_if_confirmation_email_fails() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    // Fail every attempt the email client makes
+    app.outbox.fail_next_attempts(usize::MAX);
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.text().await.unwrap(), "");
+}
+
+/// Check that signing up again while pending re-sends the confirmation email,
+/// without storing another subscriber.
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(app.wait_for_messages(2).await.len(), 2);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+}
+
+/// Check that signing up again once confirmed returns the same response as a new signup,
+/// without sending another email.
+#[tokio::test]
+async fn subscribing_when_already_confirmed_returns_200_without_sending_an_email() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let confirmation_links = app.get_confirmation_links(&app.wait_for_messages(1).await[0]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.text().await.unwrap(), "");
+    assert_eq!(app.outbox.messages().len(), 1);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+/// Check that signing up again after unsubscribing sends a new confirmation email,
+/// which confirms the subscriber again.
+#[tokio::test]
+async fn subscribing_after_unsubscribing_requires_a_fresh_confirmation() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let unsubscribe_link = app.get_unsubscribe_link(&app.wait_for_messages(1).await[0]);
+    reqwest::get(unsubscribe_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    let confirmation_links = app.get_confirmation_links(&app.wait_for_messages(2).await[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
 }
 
 /// Check that `/subscriptions` endpoint returns `400 BAD REQUEST` when invalid data was posted.
@@ -41,23 +184,39 @@ async fn subscribe_return_400_for_missing_data() {
             "The API did not fail with 400 Bad Request when the payload was {}.",
             error_message
         );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
     }
 }
 
-/// Ensure that empty fields and wrong emails are causing `400 BAD REQUEST` errors.
+/// Ensure that empty fields and wrong emails are causing `400 BAD REQUEST` errors,
+/// with problem details naming the invalid field.
 #[tokio::test]
 async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
     // Arrange
     let app = spawn_app().await;
     let test_cases = vec![
-        ("name=&email=hazadus7%40gmail.com", "empty name"),
-        ("name=hazadus&email=", "empty email"),
+        ("name=&email=hazadus7%40gmail.com", "name", "empty name"),
+        ("name=hazadus&email=", "email", "empty email"),
         (
             "name=hazadus&email=definitely-not-an-email",
+            "email",
             "invalid email",
         ),
+        (
+            "name=%3Cscript%3E&email=hazadus7%40gmail.com",
+            "name",
+            "name with forbidden characters",
+        ),
+        (
+            "name=hazadus&email=hazadus7%40gmail.com&time_zone=Mars%2FOlympus",
+            "time_zone",
+            "unknown time zone",
+        ),
     ];
-    for (body, description) in test_cases {
+    for (body, field, description) in test_cases {
         // Act
         let response = app.post_subscriptions(body.into()).await;
 
@@ -68,5 +227,17 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
             "The API did not return a 400 BAD REQUEST when the payload was {}.",
             description
         );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+        assert_eq!(
+            problem["invalid-params"][0]["name"], field,
+            "The problem details did not name the invalid field when the payload was {}.",
+            description
+        );
+        assert!(problem["invalid-params"][0]["reason"].is_string());
     }
 }
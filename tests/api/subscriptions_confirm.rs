@@ -0,0 +1,124 @@
+//! Contains tests for `/subscriptions/confirm` endpoint.
+use crate::helpers::spawn_app;
+
+/// Check that confirmation requests without a token are rejected with `400 BAD REQUEST`.
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+/// Check that confirmation requests with an unknown token are rejected with `401 UNAUTHORIZED`.
+#[tokio::test]
+async fn confirmations_with_unknown_token_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+/// Check that the link returned by `/subscriptions` returns `200 OK` if called.
+#[tokio::test]
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+/// Check that clicking on the confirmation link confirms the subscriber.
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+
+    assert_eq!(saved.email, "hazadus7@gmail.com");
+    assert_eq!(saved.name, "hazadus");
+    assert_eq!(saved.status, "confirmed");
+}
+
+/// Check that the confirmation time is recorded, and that the link works only once.
+#[tokio::test]
+async fn the_confirmation_link_works_once() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let confirmed_at = sqlx::query!("SELECT confirmed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .confirmed_at
+        .expect("The confirmation time wasn't recorded.");
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT confirmed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.confirmed_at, Some(confirmed_at));
+}
+
+/// Check that the confirmation link doesn't subscribe again someone who has unsubscribed.
+#[tokio::test]
+async fn the_confirmation_link_doesnt_resubscribe_after_unsubscribing() {
+    let app = spawn_app().await;
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let email = &app.wait_for_messages(1).await[0];
+    let confirmation_links = app.get_confirmation_links(email);
+    reqwest::get(app.get_unsubscribe_link(email))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
@@ -0,0 +1,266 @@
+//! Contains tests for `/subscriptions/data_requests`, `/subscriptions/data`
+//! and `/subscriptions/erase` endpoints.
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Sign up `hazadus`, without confirming.
+async fn create_subscriber(app: &TestApp) {
+    app.post_subscriptions("name=hazadus&email=hazadus7%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.wait_for_messages(1).await;
+}
+
+/// Post a data request of `kind` for `email`.
+async fn post_data_request(app: &TestApp, email: &str, kind: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions/data_requests", &app.address))
+        .form(&serde_json::json!({"email": email, "kind": kind}))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Request data of `kind` for `hazadus`, and return the link from the email sent to them.
+async fn data_request_link(app: &TestApp, kind: &str) -> reqwest::Url {
+    let n_sent = app.outbox.messages().len();
+    post_data_request(app, "hazadus7@gmail.com", kind)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = app.wait_for_messages(n_sent + 1).await.pop().unwrap();
+    assert_eq!(email.to, "hazadus7@gmail.com");
+    app.get_confirmation_links(&email).html
+}
+
+/// Return the `data_request_token` query parameter of `link`.
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(name, _)| name == "data_request_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+/// Post the erasure form with `data_request_token`.
+async fn post_erase(app: &TestApp, data_request_token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions/erase", &app.address))
+        .form(&serde_json::json!({"data_request_token": data_request_token}))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Check that the access link returns everything stored about the subscriber.
+#[tokio::test]
+async fn the_access_link_returns_the_subscriber_data() {
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+
+    let link = data_request_link(&app, "access").await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "hazadus7@gmail.com");
+    assert_eq!(data["subscription"]["status"], "pending_confirmation");
+    assert_eq!(data["subscription"]["delivery_frequency"], "immediately");
+    assert!(data["subscription"]["time_zone"].is_null());
+    assert_eq!(
+        data["lists"],
+        serde_json::json!(["events", "release-notes", "weekly-digest"])
+    );
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["data_requests"][0]["kind"], "access");
+    assert!(data["pending_deliveries"].as_array().unwrap().is_empty());
+}
+
+/// Check that requests for unknown emails succeed, but send nothing.
+#[tokio::test]
+async fn requests_for_unknown_emails_send_nothing() {
+    let app = spawn_app().await;
+
+    let response = post_data_request(&app, "stranger@example.com", "access").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    // The unknown request only needs a lookup: it is done by the time a later email is out.
+    create_subscriber(&app).await;
+    data_request_link(&app, "access").await;
+    assert!(app
+        .outbox
+        .messages()
+        .iter()
+        .all(|m| m.to == "hazadus7@gmail.com"));
+}
+
+/// Check that the response doesn't reveal a known email by failing to send to it.
+#[tokio::test]
+async fn requests_succeed_when_the_email_fails() {
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    app.outbox.reject_next_attempts(1);
+
+    let response = post_data_request(&app, "hazadus7@gmail.com", "access").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+/// Check that requests with an invalid email or kind are rejected.
+#[tokio::test]
+async fn invalid_requests_are_rejected() {
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let test_cases = vec![
+        ("not-an-email", "access", "invalid email"),
+        ("hazadus7@gmail.com", "rectification", "unknown kind"),
+    ];
+
+    for (email, kind, description) in test_cases {
+        let response = post_data_request(&app, email, kind).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The request wasn't rejected with {}.",
+            description
+        );
+    }
+}
+
+/// Check that tokens only work for the kind of request they were issued for,
+/// and unknown or expired tokens don't work at all.
+#[tokio::test]
+async fn tokens_are_checked() {
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let access_link = data_request_link(&app, "access").await;
+    let erasure_link = data_request_link(&app, "erasure").await;
+
+    let mut data_with_erasure_token = access_link.clone();
+    data_with_erasure_token
+        .query_pairs_mut()
+        .clear()
+        .append_pair("data_request_token", &token(&erasure_link));
+    let response = reqwest::get(data_with_erasure_token).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = post_erase(&app, &token(&access_link)).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = post_erase(&app, "unknown").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    sqlx::query!("UPDATE data_request_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = reqwest::get(access_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(erasure_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+/// Check that the erasure deletes every row about the subscriber, and only keeps
+/// an audit record without personal data.
+#[tokio::test]
+async fn erasure_deletes_everything_about_the_subscriber() {
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, status, published_at)
+        VALUES ($1, 'Title', 'Text', '<p>HTML</p>', 'published', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, execute_after)
+        VALUES ($1, 'hazadus7@gmail.com', now() + interval '1 hour')
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // A saved publish report, replayed for retries, listing the subscriber as skipped.
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency
+            (user_id, idempotency_key, response_status_code, response_headers, response_body,
+             created_at)
+        SELECT user_id, 'key', 200, '{}', $1, now() FROM users LIMIT 1
+        "#,
+        br#"{"queued":0,"skipped":[{"email":"hazadus7@gmail.com","reason":"invalid"}]}"#.to_vec()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let link = data_request_link(&app, "erasure").await;
+    let form = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(form.status().as_u16(), 200);
+    assert!(form.text().await.unwrap().contains(&token(&link)));
+    let response = post_erase(&app, &token(&link)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your data has been erased."));
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "subscription_tokens!",
+            (SELECT COUNT(*) FROM data_request_tokens) AS "data_request_tokens!",
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "issue_delivery_queue!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            remaining.subscriptions,
+            remaining.subscription_tokens,
+            remaining.data_request_tokens,
+            remaining.issue_delivery_queue
+        ),
+        (0, 0, 0, 0)
+    );
+    let saved_report = sqlx::query!("SELECT response_body FROM idempotency")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved_report.response_body.unwrap(),
+        br#"{"queued":0,"skipped":[{"email":"[erased]","reason":"invalid"}]}"#.to_vec()
+    );
+    let erasure = sqlx::query!("SELECT deleted_rows FROM subscriber_erasures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        erasure.deleted_rows,
+        serde_json::json!({
+            "subscriptions": 1,
+            "subscription_lists": 3,
+            "subscription_tokens": 1,
+            "data_request_tokens": 1,
+            "issue_delivery_queue": 1,
+            "idempotency": 1,
+        })
+    );
+
+    // The link is spent, and the email can sign up again.
+    let response = post_erase(&app, &token(&link)).await;
+    assert_eq!(response.status().as_u16(), 401);
+    create_subscriber(&app).await;
+}
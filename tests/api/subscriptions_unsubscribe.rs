@@ -0,0 +1,77 @@
+//! Contains tests for `/subscriptions/unsubscribe` endpoint.
+use crate::helpers::{spawn_app, TestApp};
+
+/// Subscribe and return the unsubscribe link from the confirmation email.
+async fn subscribe_and_get_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    let body = "name=hazadus&email=hazadus7%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+    let email = &app.wait_for_messages(1).await[0];
+    app.get_unsubscribe_link(email)
+}
+
+/// Check that unsubscribe requests without a token are rejected with `400 BAD REQUEST`.
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+/// Check that unsubscribe requests with an unknown token are rejected with `401 UNAUTHORIZED`.
+#[tokio::test]
+async fn unsubscribe_with_unknown_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+/// Check that following the unsubscribe link marks the subscriber as unsubscribed,
+/// without deleting the row.
+#[tokio::test]
+async fn following_the_unsubscribe_link_unsubscribes_a_subscriber() {
+    let app = spawn_app().await;
+    let unsubscribe_link = subscribe_and_get_unsubscribe_link(&app).await;
+
+    let response = reqwest::get(unsubscribe_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+/// Check that RFC 8058 one-click `POST` to the unsubscribe link unsubscribes the subscriber.
+#[tokio::test]
+async fn one_click_post_unsubscribes_a_subscriber() {
+    let app = spawn_app().await;
+    let unsubscribe_link = subscribe_and_get_unsubscribe_link(&app).await;
+
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}